@@ -15,7 +15,7 @@ use std::{
     collections::HashMap,
     ffi::OsStr,
     fs,
-    io::{self, Read, Write},
+    io::{self, ErrorKind, Read, Write},
     panic,
     path::{Path, MAIN_SEPARATOR},
     process::{Command, Stdio},
@@ -812,7 +812,8 @@ fn has_existing_assets() -> io::Result<bool> {
     Ok(file_count == UPK_FILES.len())
 }
 
-pub fn uncook() -> io::Result<()> {
+/// `input_dir` overrides the folder in `assets.path`
+pub fn uncook(input_dir: Option<&Path>) -> io::Result<()> {
     if has_existing_assets().unwrap_or_default() {
         info!("Found existing assets");
         return Ok(());
@@ -835,7 +836,16 @@ pub fn uncook() -> io::Result<()> {
         return Ok(());
     }
 
-    let input_dir = get_input_dir();
+    let input_dir = match input_dir {
+        Some(dir) if dir.is_dir() => dir.to_string_lossy().to_string(),
+        Some(dir) => {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("Couldn't find the assets directory {}", dir.display()),
+            ))
+        }
+        None => get_input_dir(),
+    };
 
     info!("Uncooking assets from Rocket League...");
 
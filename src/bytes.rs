@@ -1,21 +1,46 @@
 use crate::{
-    renderer::{CustomColor as Color, Render, RenderMessage},
+    renderer::{Anchor, CustomColor as Color, GroupInfo, Lifetime, Render, RenderMessage},
     rocketsim::{
         BallHitInfo, BallState, BoostPad, BoostPadState, CarConfig, CarContact, CarControls, CarInfo, CarState, GameMode,
         GameState, HeatseekerInfo, Team, WheelPairConfig, WorldContact,
     },
+    udp::{FrameHeader, Handshake},
 };
 use bevy::math::{Mat3A as RotMat, Vec2, Vec3 as BVec3, Vec3A as Vec3};
 use core::fmt;
+use thiserror::Error;
+
+/// Why a packet couldn't be decoded
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum DecodeError {
+    #[error("Packet is truncated, needed {needed} more bytes but only {available} are left")]
+    Truncated { needed: usize, available: usize },
+    #[error("Invalid {name} tag {tag}")]
+    BadTag { name: &'static str, tag: u8 },
+    #[error("Packet is {actual} bytes long, but its header says it should be {expected}")]
+    SizeMismatch { expected: usize, actual: usize },
+}
 
-pub trait FromBytes {
-    fn from_bytes(bytes: &[u8]) -> Self;
+pub trait FromBytes: Sized {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError>;
 }
 
 pub trait FromBytesExact: FromBytes {
     const NUM_BYTES: usize;
 }
 
+/// The first `N` bytes, or an error if there aren't enough
+#[inline]
+fn take<const N: usize>(bytes: &[u8]) -> Result<[u8; N], DecodeError> {
+    bytes
+        .get(..N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(DecodeError::Truncated {
+            needed: N,
+            available: bytes.len(),
+        })
+}
+
 struct ByteReader<'a> {
     idx: usize,
     bytes: &'a [u8],
@@ -27,11 +52,35 @@ impl<'a> ByteReader<'a> {
         Self { idx: 0, bytes }
     }
 
-    #[track_caller]
-    pub fn read<I: FromBytesExact>(&mut self) -> I {
-        let item = I::from_bytes(&self.bytes[self.idx..self.idx + I::NUM_BYTES]);
+    pub fn read<I: FromBytesExact>(&mut self) -> Result<I, DecodeError> {
+        let bytes = self
+            .bytes
+            .get(self.idx..self.idx + I::NUM_BYTES)
+            .ok_or(DecodeError::Truncated {
+                needed: I::NUM_BYTES,
+                available: self.bytes.len().saturating_sub(self.idx),
+            })?;
+
+        let item = I::from_bytes(bytes)?;
         self.idx += I::NUM_BYTES;
-        item
+        Ok(item)
+    }
+
+    /// Read `num` items that were prefixed by their count
+    pub fn read_many<I: FromBytesExact, C: FromIterator<I>>(&mut self, num: usize) -> Result<C, DecodeError> {
+        (0..num).map(|_| self.read()).collect()
+    }
+
+    /// Read UTF-8 text that was prefixed by its length in bytes, replacing any invalid characters
+    pub fn read_string(&mut self) -> Result<String, DecodeError> {
+        let num_bytes = self.read::<u16>()? as usize;
+        let bytes = self.bytes.get(self.idx..self.idx + num_bytes).ok_or(DecodeError::Truncated {
+            needed: num_bytes,
+            available: self.bytes.len().saturating_sub(self.idx),
+        })?;
+
+        self.idx += num_bytes;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
     }
 
     #[inline]
@@ -43,8 +92,8 @@ impl<'a> ByteReader<'a> {
 
 impl FromBytes for bool {
     #[inline]
-    fn from_bytes(bytes: &[u8]) -> Self {
-        bytes[0] != 0
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(take::<1>(bytes)?[0] != 0)
     }
 }
 
@@ -52,83 +101,34 @@ impl FromBytesExact for bool {
     const NUM_BYTES: usize = 1;
 }
 
-impl FromBytesExact for f32 {
-    const NUM_BYTES: usize = 4;
-}
-
-impl FromBytes for f32 {
-    #[inline]
-    fn from_bytes(bytes: &[u8]) -> Self {
-        Self::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-    }
-}
-
-impl FromBytesExact for u8 {
-    const NUM_BYTES: usize = 1;
-}
-
-impl FromBytes for u8 {
-    #[inline]
-    fn from_bytes(bytes: &[u8]) -> Self {
-        bytes[0]
-    }
-}
-
-impl FromBytesExact for u16 {
-    const NUM_BYTES: usize = 2;
-}
-
-impl FromBytes for u16 {
-    #[inline]
-    fn from_bytes(bytes: &[u8]) -> Self {
-        Self::from_le_bytes([bytes[0], bytes[1]])
-    }
-}
-
-impl FromBytesExact for u32 {
-    const NUM_BYTES: usize = 4;
-}
-
-impl FromBytes for u32 {
-    #[inline]
-    fn from_bytes(bytes: &[u8]) -> Self {
-        Self::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-    }
-}
-
-impl FromBytesExact for u64 {
-    const NUM_BYTES: usize = 8;
-}
-
-impl FromBytes for u64 {
-    #[inline]
-    fn from_bytes(bytes: &[u8]) -> Self {
-        Self::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]])
-    }
-}
+macro_rules! impl_from_bytes_via_std {
+    ($($t:ty),+) => {
+        $(impl FromBytes for $t {
+            #[inline]
+            fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+                Ok(Self::from_le_bytes(take(bytes)?))
+            }
+        }
 
-impl FromBytesExact for i32 {
-    const NUM_BYTES: usize = 4;
+        impl FromBytesExact for $t {
+            const NUM_BYTES: usize = core::mem::size_of::<$t>();
+        })+
+    };
 }
 
-impl FromBytes for i32 {
-    #[inline]
-    fn from_bytes(bytes: &[u8]) -> Self {
-        Self::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-    }
-}
+impl_from_bytes_via_std!(u8, u16, u32, u64, i32, f32);
 
 impl<T: FromBytesExact + fmt::Debug, const N: usize> FromBytesExact for [T; N] {
     const NUM_BYTES: usize = T::NUM_BYTES * N;
 }
 
 impl<T: FromBytesExact + fmt::Debug, const N: usize> FromBytes for [T; N] {
-    fn from_bytes(bytes: &[u8]) -> Self {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
         let mut reader = ByteReader::new(bytes);
 
-        let items = (0..N).map(|_| reader.read()).collect::<Vec<T>>();
+        let items = reader.read_many::<T, Vec<T>>(N)?;
         reader.debug_assert_num_bytes(Self::NUM_BYTES);
-        items.try_into().unwrap()
+        Ok(items.try_into().unwrap())
     }
 }
 
@@ -138,11 +138,11 @@ impl FromBytesExact for Team {
 
 impl FromBytes for Team {
     #[inline]
-    fn from_bytes(bytes: &[u8]) -> Self {
-        match bytes[0] {
-            0 => Self::Blue,
-            1 => Self::Orange,
-            _ => unreachable!(),
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match take::<1>(bytes)?[0] {
+            0 => Ok(Self::Blue),
+            1 => Ok(Self::Orange),
+            tag => Err(DecodeError::BadTag { name: "team", tag }),
         }
     }
 }
@@ -153,14 +153,14 @@ impl FromBytesExact for GameMode {
 
 impl FromBytes for GameMode {
     #[inline]
-    fn from_bytes(bytes: &[u8]) -> Self {
-        match bytes[0] {
-            0 => Self::Soccar,
-            1 => Self::Hoops,
-            2 => Self::HeatSeeker,
-            3 => Self::Snowday,
-            4 => Self::TheVoid,
-            _ => unreachable!(),
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match take::<1>(bytes)?[0] {
+            0 => Ok(Self::Soccar),
+            1 => Ok(Self::Hoops),
+            2 => Ok(Self::HeatSeeker),
+            3 => Ok(Self::Snowday),
+            4 => Ok(Self::TheVoid),
+            tag => Err(DecodeError::BadTag { name: "game mode", tag }),
         }
     }
 }
@@ -170,9 +170,9 @@ impl FromBytesExact for Vec3 {
 }
 
 impl FromBytes for Vec3 {
-    fn from_bytes(bytes: &[u8]) -> Self {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
         let mut reader = ByteReader::new(bytes);
-        Self::new(reader.read(), reader.read(), reader.read())
+        Ok(Self::new(reader.read()?, reader.read()?, reader.read()?))
     }
 }
 
@@ -181,9 +181,9 @@ impl FromBytesExact for BVec3 {
 }
 
 impl FromBytes for BVec3 {
-    fn from_bytes(bytes: &[u8]) -> Self {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
         let mut reader = ByteReader::new(bytes);
-        Self::new(reader.read(), reader.read(), reader.read())
+        Ok(Self::new(reader.read()?, reader.read()?, reader.read()?))
     }
 }
 
@@ -192,9 +192,9 @@ impl FromBytesExact for Vec2 {
 }
 
 impl FromBytes for Vec2 {
-    fn from_bytes(bytes: &[u8]) -> Self {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
         let mut reader = ByteReader::new(bytes);
-        Self::new(reader.read(), reader.read())
+        Ok(Self::new(reader.read()?, reader.read()?))
     }
 }
 
@@ -203,22 +203,22 @@ impl FromBytesExact for Color {
 }
 
 impl FromBytes for Color {
-    fn from_bytes(bytes: &[u8]) -> Self {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
         let mut reader = ByteReader::new(bytes);
-        Self::rgba(reader.read(), reader.read(), reader.read(), reader.read())
+        Ok(Self::rgba(reader.read()?, reader.read()?, reader.read()?, reader.read()?))
     }
 }
 
 macro_rules! impl_from_bytes_exact {
     ($t:ty, $n:expr, $($p:ident),+) => {
         impl FromBytes for $t {
-            fn from_bytes(bytes: &[u8]) -> Self {
+            fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
                 let mut reader = ByteReader::new(bytes);
                 let item = Self {
-                    $($p: reader.read(),)+
+                    $($p: reader.read()?,)+
                 };
                 reader.debug_assert_num_bytes(Self::NUM_BYTES);
-                item
+                Ok(item)
             }
         }
 
@@ -314,6 +314,15 @@ macro_rules! impl_bytes_exact {
 }
 
 impl_bytes_exact!(RotMat, Vec3::NUM_BYTES * 3, x_axis, y_axis, z_axis);
+impl_bytes_exact!(Handshake, u16::NUM_BYTES + u32::NUM_BYTES, version, capabilities);
+impl_bytes_exact!(
+    FrameHeader,
+    u16::NUM_BYTES + 1 + u32::NUM_BYTES * 2,
+    magic,
+    packet_type,
+    len,
+    seq
+);
 impl_bytes_exact!(
     HeatseekerInfo,
     f32::NUM_BYTES * 3,
@@ -438,34 +447,206 @@ impl_bytes_exact!(
     config
 );
 
+impl Anchor {
+    const fn count_bytes(self) -> usize {
+        match self {
+            Self::World | Self::Ball => 1,
+            Self::Car { .. } => 1 + u32::NUM_BYTES,
+        }
+    }
+
+    fn from_reader(reader: &mut ByteReader) -> Result<Self, DecodeError> {
+        Ok(match reader.read::<u8>()? {
+            0 => Self::World,
+            1 => Self::Ball,
+            2 => Self::Car {
+                id: reader.read()?,
+                local: false,
+            },
+            3 => Self::Car {
+                id: reader.read()?,
+                local: true,
+            },
+            tag => return Err(DecodeError::BadTag { name: "anchor", tag }),
+        })
+    }
+
+    fn write_bytes(self, bytes: &mut Vec<u8>) {
+        match self {
+            Self::World => bytes.push(0),
+            Self::Ball => bytes.push(1),
+            Self::Car { id, local } => {
+                bytes.push(if local { 3 } else { 2 });
+                bytes.extend_from_slice(&id.to_bytes());
+            }
+        }
+    }
+}
+
 impl Render {
     fn count_bytes(&self) -> usize {
         match self {
             Self::Line2D { .. } => 1 + Vec2::NUM_BYTES * 2 + Color::NUM_BYTES,
             Self::Line { .. } => 1 + Vec3::NUM_BYTES * 2 + Color::NUM_BYTES,
             Self::LineStrip { positions, .. } => 1 + u16::NUM_BYTES + positions.len() * Vec3::NUM_BYTES + Color::NUM_BYTES,
+            Self::Sphere { .. } => 1 + Vec3::NUM_BYTES + f32::NUM_BYTES + Color::NUM_BYTES,
+            Self::Box { .. } | Self::Arrow { .. } => 1 + Vec3::NUM_BYTES * 2 + Color::NUM_BYTES,
+            Self::OrientedBox { .. } => 1 + Vec3::NUM_BYTES * 2 + RotMat::NUM_BYTES + Color::NUM_BYTES,
+            Self::Circle { .. } => 1 + Vec3::NUM_BYTES * 2 + f32::NUM_BYTES + Color::NUM_BYTES,
+            Self::Ring { .. } => 1 + Vec3::NUM_BYTES * 2 + f32::NUM_BYTES * 2 + Color::NUM_BYTES,
+            Self::Arc { .. } => 1 + Vec3::NUM_BYTES * 3 + f32::NUM_BYTES + Color::NUM_BYTES,
+            Self::Text2D { text, .. } => {
+                1 + Vec2::NUM_BYTES + f32::NUM_BYTES + Color::NUM_BYTES + u16::NUM_BYTES + truncate_string(text).len()
+            }
+            Self::Text3D { text, .. } => {
+                1 + Vec3::NUM_BYTES + f32::NUM_BYTES + Color::NUM_BYTES + u16::NUM_BYTES + truncate_string(text).len()
+            }
+            Self::Anchored { anchor, render } => 1 + anchor.count_bytes() + render.count_bytes(),
+            Self::Triangles {
+                positions,
+                vertex_colors,
+                ..
+            } => {
+                1 + u16::NUM_BYTES
+                    + positions.len() * Vec3::NUM_BYTES
+                    + Color::NUM_BYTES
+                    + bool::NUM_BYTES
+                    + vertex_colors
+                        .as_ref()
+                        .filter(|colors| colors.len() == positions.len())
+                        .map_or(0, |_| positions.len() * Color::NUM_BYTES)
+            }
+            Self::Quad { .. } => 1 + Vec3::NUM_BYTES * 4 + Color::NUM_BYTES,
         }
     }
 
-    fn from_reader(reader: &mut ByteReader) -> Self {
-        match reader.read::<u8>() {
+    fn from_reader(reader: &mut ByteReader) -> Result<Self, DecodeError> {
+        let tag = reader.read()?;
+        Self::from_tag(tag, reader)
+    }
+
+    fn from_tag(tag: u8, reader: &mut ByteReader) -> Result<Self, DecodeError> {
+        Ok(match tag {
             0 => Self::Line2D {
-                start: reader.read(),
-                end: reader.read(),
-                color: reader.read(),
+                start: reader.read()?,
+                end: reader.read()?,
+                color: reader.read()?,
             },
             1 => Self::Line {
-                start: reader.read(),
-                end: reader.read(),
-                color: reader.read(),
+                start: reader.read()?,
+                end: reader.read()?,
+                color: reader.read()?,
             },
             2 => Self::LineStrip {
-                positions: (0..reader.read::<u16>()).map(|_| reader.read()).collect(),
-                color: reader.read(),
+                positions: {
+                    let num_positions = reader.read::<u16>()?;
+                    reader.read_many(num_positions as usize)?
+                },
+                color: reader.read()?,
             },
-            _ => unreachable!(),
-        }
+            3 => Self::Sphere {
+                center: reader.read()?,
+                radius: reader.read()?,
+                color: reader.read()?,
+            },
+            4 => Self::Box {
+                center: reader.read()?,
+                half_extents: reader.read()?,
+                color: reader.read()?,
+            },
+            5 => Self::OrientedBox {
+                center: reader.read()?,
+                half_extents: reader.read()?,
+                rotation: reader.read()?,
+                color: reader.read()?,
+            },
+            6 => Self::Circle {
+                center: reader.read()?,
+                normal: reader.read()?,
+                radius: reader.read()?,
+                color: reader.read()?,
+            },
+            7 => Self::Ring {
+                center: reader.read()?,
+                normal: reader.read()?,
+                inner_radius: reader.read()?,
+                outer_radius: reader.read()?,
+                color: reader.read()?,
+            },
+            8 => Self::Arc {
+                center: reader.read()?,
+                normal: reader.read()?,
+                start: reader.read()?,
+                angle: reader.read()?,
+                color: reader.read()?,
+            },
+            9 => Self::Arrow {
+                start: reader.read()?,
+                end: reader.read()?,
+                color: reader.read()?,
+            },
+            10 => Self::Text2D {
+                position: reader.read()?,
+                size: reader.read()?,
+                color: reader.read()?,
+                text: reader.read_string()?,
+            },
+            11 => Self::Text3D {
+                position: reader.read()?,
+                size: reader.read()?,
+                color: reader.read()?,
+                text: reader.read_string()?,
+            },
+            12 => Self::Anchored {
+                anchor: Anchor::from_reader(reader)?,
+                render: match reader.read::<u8>()? {
+                    // anchors can't be nested, which also keeps a packet from recursing arbitrarily deep
+                    12 => {
+                        return Err(DecodeError::BadTag {
+                            name: "anchored render",
+                            tag: 12,
+                        })
+                    }
+                    tag => Box::new(Self::from_tag(tag, reader)?),
+                },
+            },
+            13 => {
+                let num_positions = reader.read::<u16>()? as usize;
+
+                Self::Triangles {
+                    positions: reader.read_many(num_positions)?,
+                    color: reader.read()?,
+                    // there's either a color for every position or none at all
+                    vertex_colors: if reader.read()? {
+                        Some(reader.read_many(num_positions)?)
+                    } else {
+                        None
+                    },
+                }
+            }
+            14 => Self::Quad {
+                corners: reader.read()?,
+                color: reader.read()?,
+            },
+            tag => return Err(DecodeError::BadTag { name: "render", tag }),
+        })
+    }
+}
+
+/// The length is written as a `u16`, so longer text is cut off at a character boundary
+fn truncate_string(text: &str) -> &str {
+    let mut len = text.len().min(u16::MAX as usize);
+    while !text.is_char_boundary(len) {
+        len -= 1;
     }
+
+    &text[..len]
+}
+
+fn write_string(bytes: &mut Vec<u8>, text: &str) {
+    let text = truncate_string(text);
+    bytes.extend_from_slice(&(text.len() as u16).to_bytes());
+    bytes.extend_from_slice(text.as_bytes());
 }
 
 impl ToBytes for Render {
@@ -494,6 +675,140 @@ impl ToBytes for Render {
                     bytes.extend_from_slice(&pos.to_bytes());
                 }
 
+                bytes.extend_from_slice(&color.to_bytes());
+            }
+            Render::Sphere { center, radius, color } => {
+                bytes.push(3);
+                bytes.extend_from_slice(&center.to_bytes());
+                bytes.extend_from_slice(&radius.to_bytes());
+                bytes.extend_from_slice(&color.to_bytes());
+            }
+            Render::Box {
+                center,
+                half_extents,
+                color,
+            } => {
+                bytes.push(4);
+                bytes.extend_from_slice(&center.to_bytes());
+                bytes.extend_from_slice(&half_extents.to_bytes());
+                bytes.extend_from_slice(&color.to_bytes());
+            }
+            Render::OrientedBox {
+                center,
+                half_extents,
+                rotation,
+                color,
+            } => {
+                bytes.push(5);
+                bytes.extend_from_slice(&center.to_bytes());
+                bytes.extend_from_slice(&half_extents.to_bytes());
+                bytes.extend_from_slice(&rotation.to_bytes());
+                bytes.extend_from_slice(&color.to_bytes());
+            }
+            Render::Circle {
+                center,
+                normal,
+                radius,
+                color,
+            } => {
+                bytes.push(6);
+                bytes.extend_from_slice(&center.to_bytes());
+                bytes.extend_from_slice(&normal.to_bytes());
+                bytes.extend_from_slice(&radius.to_bytes());
+                bytes.extend_from_slice(&color.to_bytes());
+            }
+            Render::Ring {
+                center,
+                normal,
+                inner_radius,
+                outer_radius,
+                color,
+            } => {
+                bytes.push(7);
+                bytes.extend_from_slice(&center.to_bytes());
+                bytes.extend_from_slice(&normal.to_bytes());
+                bytes.extend_from_slice(&inner_radius.to_bytes());
+                bytes.extend_from_slice(&outer_radius.to_bytes());
+                bytes.extend_from_slice(&color.to_bytes());
+            }
+            Render::Arc {
+                center,
+                normal,
+                start,
+                angle,
+                color,
+            } => {
+                bytes.push(8);
+                bytes.extend_from_slice(&center.to_bytes());
+                bytes.extend_from_slice(&normal.to_bytes());
+                bytes.extend_from_slice(&start.to_bytes());
+                bytes.extend_from_slice(&angle.to_bytes());
+                bytes.extend_from_slice(&color.to_bytes());
+            }
+            Render::Arrow { start, end, color } => {
+                bytes.push(9);
+                bytes.extend_from_slice(&start.to_bytes());
+                bytes.extend_from_slice(&end.to_bytes());
+                bytes.extend_from_slice(&color.to_bytes());
+            }
+            Render::Text2D {
+                position,
+                size,
+                color,
+                text,
+            } => {
+                bytes.push(10);
+                bytes.extend_from_slice(&position.to_bytes());
+                bytes.extend_from_slice(&size.to_bytes());
+                bytes.extend_from_slice(&color.to_bytes());
+                write_string(&mut bytes, text);
+            }
+            Render::Text3D {
+                position,
+                size,
+                color,
+                text,
+            } => {
+                bytes.push(11);
+                bytes.extend_from_slice(&position.to_bytes());
+                bytes.extend_from_slice(&size.to_bytes());
+                bytes.extend_from_slice(&color.to_bytes());
+                write_string(&mut bytes, text);
+            }
+            Render::Anchored { anchor, render } => {
+                bytes.push(12);
+                anchor.write_bytes(&mut bytes);
+                bytes.extend_from_slice(&render.to_bytes());
+            }
+            Render::Triangles {
+                positions,
+                color,
+                vertex_colors,
+            } => {
+                bytes.push(13);
+                bytes.extend_from_slice(&(positions.len() as u16).to_bytes());
+
+                for pos in positions {
+                    bytes.extend_from_slice(&pos.to_bytes());
+                }
+
+                bytes.extend_from_slice(&color.to_bytes());
+
+                // the count is shared with the positions, so colors that don't match up aren't sent
+                let vertex_colors = vertex_colors.as_ref().filter(|colors| colors.len() == positions.len());
+                bytes.extend_from_slice(&vertex_colors.is_some().to_bytes());
+
+                for vertex_color in vertex_colors.into_iter().flatten() {
+                    bytes.extend_from_slice(&vertex_color.to_bytes());
+                }
+            }
+            Render::Quad { corners, color } => {
+                bytes.push(14);
+
+                for corner in corners {
+                    bytes.extend_from_slice(&corner.to_bytes());
+                }
+
                 bytes.extend_from_slice(&color.to_bytes());
             }
         }
@@ -505,39 +820,113 @@ impl ToBytes for Render {
 }
 
 impl FromBytes for RenderMessage {
-    fn from_bytes(bytes: &[u8]) -> Self {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
         let mut reader = ByteReader::new(bytes);
-        reader.read::<u32>();
-
-        match reader.read::<u8>() {
-            0 => Self::AddRender(
-                reader.read(),
-                (0..reader.read::<u16>()).map(|_| Render::from_reader(&mut reader)).collect(),
-            ),
-            1 => Self::RemoveRender(reader.read()),
-            _ => unreachable!(),
+
+        let num_bytes = reader.read::<u32>()? as usize;
+        if num_bytes != bytes.len() && !Self::is_legacy_remove_render(num_bytes, bytes) {
+            return Err(DecodeError::SizeMismatch {
+                expected: num_bytes,
+                actual: bytes.len(),
+            });
+        }
+
+        match reader.read::<u8>()? {
+            tag @ (0 | 2) => {
+                let id = reader.read()?;
+                let lifetime = if tag == 2 {
+                    Some(Lifetime::from_reader(&mut reader)?)
+                } else {
+                    None
+                };
+
+                let num_renders = reader.read::<u16>()?;
+                let renders = (0..num_renders)
+                    .map(|_| Render::from_reader(&mut reader))
+                    .collect::<Result<_, _>>()?;
+
+                Ok(Self::AddRender(id, renders, lifetime))
+            }
+            1 => Ok(Self::RemoveRender(reader.read()?)),
+            3 => Ok(Self::ClearAll),
+            4 => Ok(Self::NameGroup(
+                reader.read()?,
+                GroupInfo {
+                    name: reader.read_string()?,
+                    category: reader.read_string()?,
+                },
+            )),
+            tag => Err(DecodeError::BadTag {
+                name: "render message",
+                tag,
+            }),
+        }
+    }
+}
+
+impl Lifetime {
+    const NUM_BYTES: usize = 1 + u32::NUM_BYTES;
+
+    fn from_reader(reader: &mut ByteReader) -> Result<Self, DecodeError> {
+        Ok(match reader.read::<u8>()? {
+            0 => Self::Seconds(reader.read()?),
+            1 => Self::Ticks(reader.read()?),
+            tag => return Err(DecodeError::BadTag { name: "lifetime", tag }),
+        })
+    }
+
+    fn write_bytes(self, bytes: &mut Vec<u8>) {
+        match self {
+            Self::Seconds(secs) => {
+                bytes.push(0);
+                bytes.extend_from_slice(&secs.to_bytes());
+            }
+            Self::Ticks(ticks) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&ticks.to_bytes());
+            }
         }
     }
 }
 
 impl RenderMessage {
     pub const MIN_NUM_BYTES: usize = u32::NUM_BYTES;
+    /// What older clients put in the header of a `RemoveRender`, which left out its tag
+    const LEGACY_REMOVE_RENDER_NUM_BYTES: usize = Self::MIN_NUM_BYTES + i32::NUM_BYTES;
+
+    /// Older clients still send `RemoveRender` with a header that's one byte short
+    fn is_legacy_remove_render(num_bytes: usize, bytes: &[u8]) -> bool {
+        num_bytes == Self::LEGACY_REMOVE_RENDER_NUM_BYTES
+            && bytes.len() == num_bytes + 1
+            && bytes.get(Self::MIN_NUM_BYTES) == Some(&1)
+    }
 
     fn count_bytes(&self) -> usize {
         match self {
-            Self::AddRender(_, renders) => {
+            Self::AddRender(_, renders, lifetime) => {
                 Self::MIN_NUM_BYTES
-                    + i32::NUM_BYTES
                     + 1
+                    + i32::NUM_BYTES
+                    + lifetime.map_or(0, |_| Lifetime::NUM_BYTES)
                     + u16::NUM_BYTES
                     + renders.iter().map(Render::count_bytes).sum::<usize>()
             }
-            Self::RemoveRender(_) => Self::MIN_NUM_BYTES + i32::NUM_BYTES,
+            Self::RemoveRender(_) => Self::MIN_NUM_BYTES + 1 + i32::NUM_BYTES,
+            Self::ClearAll => Self::MIN_NUM_BYTES + 1,
+            Self::NameGroup(_, info) => {
+                Self::MIN_NUM_BYTES
+                    + 1
+                    + i32::NUM_BYTES
+                    + u16::NUM_BYTES * 2
+                    + truncate_string(&info.name).len()
+                    + truncate_string(&info.category).len()
+            }
         }
     }
 
-    pub fn get_num_bytes(bytes: &[u8]) -> usize {
-        u32::from_bytes(&bytes[..u32::NUM_BYTES]) as usize
+    #[inline]
+    pub const fn get_num_bytes(bytes: &[u8; Self::MIN_NUM_BYTES]) -> usize {
+        u32::from_le_bytes(*bytes) as usize
     }
 }
 
@@ -548,9 +937,15 @@ impl ToBytes for RenderMessage {
         bytes.extend_from_slice(&(num_bytes as u32).to_bytes());
 
         match self {
-            Self::AddRender(id, renders) => {
-                bytes.push(0);
+            Self::AddRender(id, renders, lifetime) => {
+                // older clients only know the message without a lifetime
+                bytes.push(if lifetime.is_some() { 2 } else { 0 });
                 bytes.extend_from_slice(&id.to_bytes());
+
+                if let Some(lifetime) = lifetime {
+                    lifetime.write_bytes(&mut bytes);
+                }
+
                 bytes.extend_from_slice(&(renders.len() as u16).to_bytes());
                 bytes.extend(renders.iter().flat_map(ToBytes::to_bytes));
             }
@@ -558,6 +953,13 @@ impl ToBytes for RenderMessage {
                 bytes.push(1);
                 bytes.extend_from_slice(&id.to_bytes());
             }
+            Self::ClearAll => bytes.push(3),
+            Self::NameGroup(id, info) => {
+                bytes.push(4);
+                bytes.extend_from_slice(&id.to_bytes());
+                write_string(&mut bytes, &info.name);
+                write_string(&mut bytes, &info.category);
+            }
         }
 
         debug_assert_eq!(bytes.len(), num_bytes);
@@ -567,68 +969,63 @@ impl ToBytes for RenderMessage {
 }
 
 impl FromBytes for GameState {
-    #[inline]
-    fn from_bytes(bytes: &[u8]) -> Self {
-        Self {
-            tick_count: Self::read_tick_count(bytes),
-            tick_rate: Self::read_tick_rate(bytes),
-            game_mode: Self::read_game_mode(bytes),
-            ball: BallState::from_bytes(&bytes[Self::MIN_NUM_BYTES..Self::MIN_NUM_BYTES + BallState::NUM_BYTES]),
-            pads: bytes[Self::MIN_NUM_BYTES + BallState::NUM_BYTES
-                ..Self::MIN_NUM_BYTES + BallState::NUM_BYTES + Self::read_num_pads(bytes) * BoostPad::NUM_BYTES]
-                .chunks_exact(BoostPad::NUM_BYTES)
-                .map(BoostPad::from_bytes)
-                .collect(),
-            cars: bytes[Self::MIN_NUM_BYTES + BallState::NUM_BYTES + Self::read_num_pads(bytes) * BoostPad::NUM_BYTES..]
-                .chunks_exact(CarInfo::NUM_BYTES)
-                .map(CarInfo::from_bytes)
-                .collect(),
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = ByteReader::new(bytes);
+
+        let tick_count = reader.read()?;
+        let tick_rate = reader.read()?;
+        let game_mode = reader.read()?;
+        let num_pads = reader.read::<u32>()? as usize;
+        let num_cars = reader.read::<u32>()? as usize;
+
+        let num_bytes = Self::count_bytes_for(num_pads, num_cars);
+        if num_bytes != bytes.len() {
+            return Err(DecodeError::SizeMismatch {
+                expected: num_bytes,
+                actual: bytes.len(),
+            });
         }
+
+        Ok(Self {
+            tick_count,
+            tick_rate,
+            game_mode,
+            ball: reader.read()?,
+            pads: reader.read_many(num_pads)?,
+            cars: reader.read_many(num_cars)?,
+        })
     }
 }
 
 impl GameState {
     pub const MIN_NUM_BYTES: usize = u64::NUM_BYTES + f32::NUM_BYTES + 1 + u32::NUM_BYTES * 2;
 
-    fn count_bytes(&self) -> usize {
-        Self::MIN_NUM_BYTES
-            + BallState::NUM_BYTES
-            + self.pads.len() * BoostPad::NUM_BYTES
-            + self.cars.len() * CarInfo::NUM_BYTES
-    }
-
     #[inline]
-    pub fn get_num_bytes(bytes: &[u8]) -> usize {
-        Self::MIN_NUM_BYTES
-            + BallState::NUM_BYTES
-            + Self::read_num_pads(bytes) * BoostPad::NUM_BYTES
-            + Self::read_num_cars(bytes) * CarInfo::NUM_BYTES
+    const fn count_bytes_for(num_pads: usize, num_cars: usize) -> usize {
+        Self::MIN_NUM_BYTES + BallState::NUM_BYTES + num_pads * BoostPad::NUM_BYTES + num_cars * CarInfo::NUM_BYTES
     }
 
     #[inline]
-    pub fn read_tick_count(bytes: &[u8]) -> u64 {
-        u64::from_bytes(&bytes[..u64::NUM_BYTES])
-    }
-
-    #[inline]
-    pub fn read_tick_rate(bytes: &[u8]) -> f32 {
-        f32::from_bytes(&bytes[u64::NUM_BYTES..u64::NUM_BYTES + f32::NUM_BYTES])
+    fn count_bytes(&self) -> usize {
+        Self::count_bytes_for(self.pads.len(), self.cars.len())
     }
 
+    /// The full size of the packet, read from its header
     #[inline]
-    pub fn read_game_mode(bytes: &[u8]) -> GameMode {
-        GameMode::from_bytes(&bytes[(u64::NUM_BYTES + f32::NUM_BYTES)..=(u64::NUM_BYTES + f32::NUM_BYTES)])
+    pub fn get_num_bytes(bytes: &[u8; Self::MIN_NUM_BYTES]) -> usize {
+        Self::count_bytes_for(Self::read_num_pads(bytes), Self::read_num_cars(bytes))
     }
 
     #[inline]
-    pub fn read_num_pads(bytes: &[u8]) -> usize {
-        u32::from_bytes(&bytes[u64::NUM_BYTES + f32::NUM_BYTES + 1..u64::NUM_BYTES + f32::NUM_BYTES + 1 + u32::NUM_BYTES])
-            as usize
+    pub fn read_num_pads(bytes: &[u8; Self::MIN_NUM_BYTES]) -> usize {
+        const START: usize = u64::NUM_BYTES + f32::NUM_BYTES + 1;
+        u32::from_le_bytes(bytes[START..START + u32::NUM_BYTES].try_into().unwrap()) as usize
     }
 
     #[inline]
-    pub fn read_num_cars(bytes: &[u8]) -> usize {
-        u32::from_bytes(&bytes[u64::NUM_BYTES + f32::NUM_BYTES + 1 + u32::NUM_BYTES..Self::MIN_NUM_BYTES]) as usize
+    pub fn read_num_cars(bytes: &[u8; Self::MIN_NUM_BYTES]) -> usize {
+        const START: usize = u64::NUM_BYTES + f32::NUM_BYTES + 1 + u32::NUM_BYTES;
+        u32::from_le_bytes(bytes[START..Self::MIN_NUM_BYTES].try_into().unwrap()) as usize
     }
 }
 
@@ -652,3 +1049,178 @@ impl ToBytes for GameState {
         bytes
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Color = Color {
+        r: 1.,
+        g: 1.,
+        b: 1.,
+        a: 1.,
+    };
+
+    fn game_state() -> GameState {
+        GameState {
+            tick_count: 1234,
+            tick_rate: 120.,
+            game_mode: GameMode::Hoops,
+            pads: vec![BoostPad::default(); 3].into_boxed_slice(),
+            cars: vec![CarInfo::default(); 2].into_boxed_slice(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn truncated_primitive() {
+        assert_eq!(
+            u32::from_bytes(&[1, 2]),
+            Err(DecodeError::Truncated { needed: 4, available: 2 })
+        );
+    }
+
+    #[test]
+    fn bad_enum_tags() {
+        assert_eq!(Team::from_bytes(&[2]), Err(DecodeError::BadTag { name: "team", tag: 2 }));
+        assert_eq!(
+            GameMode::from_bytes(&[200]),
+            Err(DecodeError::BadTag {
+                name: "game mode",
+                tag: 200
+            })
+        );
+    }
+
+    #[test]
+    fn game_state_round_trip() {
+        let state = GameState::from_bytes(&game_state().to_bytes()).unwrap();
+
+        assert_eq!(state.tick_count, 1234);
+        assert_eq!(state.game_mode, GameMode::Hoops);
+        assert_eq!(state.pads.len(), 3);
+        assert_eq!(state.cars.len(), 2);
+    }
+
+    #[test]
+    fn truncated_game_state() {
+        let bytes = game_state().to_bytes();
+
+        assert!(matches!(
+            GameState::from_bytes(&bytes[..GameState::MIN_NUM_BYTES - 1]),
+            Err(DecodeError::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn game_state_size_mismatch() {
+        let mut bytes = game_state().to_bytes();
+        let expected = bytes.len();
+        bytes.push(0);
+
+        assert!(matches!(
+            GameState::from_bytes(&bytes),
+            Err(DecodeError::SizeMismatch { expected: e, actual }) if e == expected && actual == expected + 1
+        ));
+
+        // a header claiming a huge number of cars is rejected before anything is read
+        let mut bytes = game_state().to_bytes();
+        bytes[GameState::MIN_NUM_BYTES - u32::NUM_BYTES..GameState::MIN_NUM_BYTES].copy_from_slice(&u32::MAX.to_bytes());
+        assert!(matches!(GameState::from_bytes(&bytes), Err(DecodeError::SizeMismatch { .. })));
+    }
+
+    #[test]
+    fn render_message_round_trip() {
+        let message = RenderMessage::AddRender(
+            7,
+            vec![Render::Line {
+                start: BVec3::ZERO,
+                end: BVec3::ONE,
+                color: WHITE,
+            }],
+            Some(Lifetime::Ticks(30)),
+        );
+
+        assert!(matches!(
+            RenderMessage::from_bytes(&message.to_bytes()),
+            Ok(RenderMessage::AddRender(7, renders, Some(Lifetime::Ticks(30)))) if renders.len() == 1
+        ));
+    }
+
+    #[test]
+    fn remove_render_round_trip() {
+        assert!(matches!(
+            RenderMessage::from_bytes(&RenderMessage::RemoveRender(7).to_bytes()),
+            Ok(RenderMessage::RemoveRender(7))
+        ));
+    }
+
+    #[test]
+    fn legacy_remove_render() {
+        // the header says 8 bytes, but the tag and group id make it 9
+        let bytes = [8, 0, 0, 0, 1, 7, 0, 0, 0];
+
+        assert!(matches!(
+            RenderMessage::from_bytes(&bytes),
+            Ok(RenderMessage::RemoveRender(7))
+        ));
+        assert!(matches!(
+            RenderMessage::from_bytes(&[8, 0, 0, 0, 3, 7, 0, 0, 0]),
+            Err(DecodeError::SizeMismatch { expected: 8, actual: 9 })
+        ));
+    }
+
+    #[test]
+    fn render_message_errors() {
+        assert!(matches!(
+            RenderMessage::from_bytes(&[5, 0, 0, 0, 3]),
+            Ok(RenderMessage::ClearAll)
+        ));
+        assert!(matches!(
+            RenderMessage::from_bytes(&[5, 0, 0, 0, 9]),
+            Err(DecodeError::BadTag {
+                name: "render message",
+                tag: 9
+            })
+        ));
+        assert!(matches!(
+            RenderMessage::from_bytes(&[9, 0, 0, 0, 3]),
+            Err(DecodeError::SizeMismatch { expected: 9, actual: 5 })
+        ));
+        assert!(matches!(
+            RenderMessage::from_bytes(&[6, 0, 0, 0, 0, 1]),
+            Err(DecodeError::Truncated { needed: 4, available: 1 })
+        ));
+        assert!(matches!(
+            RenderMessage::from_bytes(&[1, 2]),
+            Err(DecodeError::Truncated { needed: 4, available: 2 })
+        ));
+    }
+
+    #[test]
+    fn bad_render_tag() {
+        let bytes = [12, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 200];
+
+        assert!(matches!(
+            RenderMessage::from_bytes(&bytes),
+            Err(DecodeError::BadTag {
+                name: "render",
+                tag: 200
+            })
+        ));
+    }
+
+    #[test]
+    fn truncated_string() {
+        // a group name that claims to be 10 bytes long, but only has 2
+        let bytes = [13, 0, 0, 0, 4, 1, 0, 0, 0, 10, 0, b'h', b'i'];
+
+        assert!(matches!(
+            RenderMessage::from_bytes(&bytes),
+            Err(DecodeError::Truncated {
+                needed: 10,
+                available: 2
+            })
+        ));
+    }
+}
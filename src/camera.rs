@@ -2,6 +2,7 @@ use crate::spectator::{Spectator, SpectatorPlugin, SpectatorSettings};
 use bevy::{
     color::palettes::css,
     core_pipeline::tonemapping::Tonemapping,
+    ecs::system::SystemParam,
     pbr::{CascadeShadowConfigBuilder, DirectionalLightShadowMap, ShadowFilteringMethod},
     prelude::*,
 };
@@ -46,6 +47,22 @@ pub enum PrimaryCamera {
     TrackCar(u32),
 }
 
+/// Where world positions are on the screen, for placing UI nodes over them before the UI is laid out
+#[derive(SystemParam)]
+pub struct WorldToUi<'w, 's> {
+    cameras: Query<'w, 's, (&'static Camera, &'static Transform), With<PrimaryCamera>>,
+}
+
+impl WorldToUi<'_, '_> {
+    /// `None` if the position is behind the camera
+    pub fn project(&self, position: Vec3) -> Option<Vec2> {
+        let (camera, transform) = self.cameras.get_single().ok()?;
+
+        // the camera has no parent, and its global transform won't be updated until after the UI is laid out
+        camera.world_to_viewport(&GlobalTransform::from(*transform), position)
+    }
+}
+
 pub const BOOST_INDICATOR_POS: Vec2 = Vec2::new(150., 150.);
 pub const BOOST_INDICATOR_FONT_SIZE: f32 = 60.0;
 pub const TIME_DISPLAY_POS: Vec2 = Vec2::new(0., 60.);
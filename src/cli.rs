@@ -0,0 +1,102 @@
+use crate::{
+    camera::PrimaryCamera,
+    stream::{StreamTransport, Transport},
+    ServerConfig,
+};
+use clap::Parser;
+use std::path::PathBuf;
+
+/// A lightweight visualizer for RocketSim that listens for packets from the simulation
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Port that the simulation listens on
+    #[arg(long, default_value_t = 34254)]
+    pub target_port: u16,
+    /// Port to listen on for packets from the simulation
+    #[arg(long, default_value_t = 45243)]
+    pub bind_port: u16,
+    /// `<target port> <bind port>`, which is how older launch scripts pass the ports
+    #[arg(hide = true, num_args = 0..=2)]
+    pub ports: Vec<u16>,
+    /// Host of the simulation, an IPv4 or IPv6 address or a host name
+    ///
+    /// Packets are sent here until a simulation sends something, and then to wherever it sent from
+    #[arg(long, default_value = "127.0.0.1")]
+    pub target: String,
+    /// Address to listen on [default: every address of the same IP version as the target]
+    #[arg(long)]
+    pub bind: Option<String>,
+    /// Receive packets over a TCP connection on the bind port instead of UDP
+    #[arg(long, conflicts_with = "unix")]
+    pub tcp: bool,
+    /// Receive packets over a Unix domain socket at this path instead of UDP
+    #[arg(long, value_name = "PATH")]
+    pub unix: Option<PathBuf>,
+    /// Settings file to load and save the options in
+    #[arg(long, value_name = "PATH", default_value = "settings.txt")]
+    pub settings: PathBuf,
+    /// Rocket League's `TAGame/CookedPCConsole` folder to uncook assets from, instead of the one in `assets.path`
+    #[arg(long, value_name = "DIR")]
+    pub assets_path: Option<PathBuf>,
+    /// Starting camera: `spectator`, `director`, or the number of a car to follow
+    #[arg(long, value_parser = parse_camera)]
+    pub camera: Option<PrimaryCamera>,
+    /// Record every received packet, to a new file in `replays/` if no path is given
+    #[arg(long, value_name = "PATH", num_args = 0..=1)]
+    pub record: Option<Option<PathBuf>>,
+    /// Play back a recording instead of listening for packets
+    #[arg(long, value_name = "PATH", conflicts_with = "headless")]
+    pub replay: Option<PathBuf>,
+    /// Record packets without opening a window, until every simulation quits
+    #[arg(long)]
+    pub headless: bool,
+    /// Width of the window
+    #[arg(long, default_value_t = 1280.)]
+    pub width: f32,
+    /// Height of the window
+    #[arg(long, default_value_t = 720.)]
+    pub height: f32,
+}
+
+fn parse_camera(value: &str) -> Result<PrimaryCamera, String> {
+    match value {
+        "spectator" => Ok(PrimaryCamera::Spectator),
+        "director" => Ok(PrimaryCamera::Director(0)),
+        _ => match value.parse() {
+            Ok(id @ 1..=8) => Ok(PrimaryCamera::TrackCar(id)),
+            _ => Err(String::from("expected `spectator`, `director` or a car number from 1 to 8")),
+        },
+    }
+}
+
+impl Cli {
+    pub fn server_config(&self) -> ServerConfig {
+        ServerConfig {
+            primary_port: self.ports.first().copied().unwrap_or(self.target_port),
+            secondary_port: self.ports.get(1).copied().unwrap_or(self.bind_port),
+            target_host: self.target.clone(),
+            bind_host: self.bind.clone(),
+        }
+    }
+
+    pub fn transport(&self) -> Transport {
+        if self.tcp {
+            return Transport::Stream(StreamTransport::Tcp);
+        }
+
+        match &self.unix {
+            #[cfg(unix)]
+            Some(path) => Transport::Stream(StreamTransport::Unix(path.clone())),
+            #[cfg(not(unix))]
+            Some(path) => {
+                println!(
+                    "Unix domain sockets aren't supported on this platform, ignoring --unix {}",
+                    path.display()
+                );
+                Transport::Udp
+            }
+            None => Transport::Udp,
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use crate::{
+    camera::WorldToUi,
+    goals::GoalScored,
+    settings::options::Options,
+    udp::{get_color_from_team, GameStateApplied, GameStates, ToBevyMat, ToBevyVec},
+};
+use bevy::{
+    color::palettes::css,
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+    ui::UiSystem,
+    utils::HashSet,
+};
+use std::time::Duration;
+
+const DEMO_EXPLOSION_RADIUS: f32 = 250.;
+const DEMO_EXPLOSION_DURATION: Duration = Duration::from_millis(600);
+const GOAL_EXPLOSION_RADIUS: f32 = 1500.;
+const GOAL_EXPLOSION_DURATION: Duration = Duration::from_millis(1500);
+const GOAL_FLASH_INTENSITY: f32 = 1e10;
+const GOAL_FLASH_DURATION: Duration = Duration::from_secs(1);
+
+#[derive(Resource)]
+struct ExplosionMesh(Handle<Mesh>);
+
+/// A sphere that quickly grows and fades away
+#[derive(Component)]
+struct Explosion {
+    timer: Timer,
+    radius: f32,
+}
+
+fn setup_explosions(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(ExplosionMesh(meshes.add(Sphere::new(1.))));
+}
+
+/// Every explosion gets its own material, so that it can fade out on its own
+fn spawn_explosion(
+    commands: &mut Commands,
+    mesh: &ExplosionMesh,
+    materials: &mut Assets<StandardMaterial>,
+    position: Vec3,
+    radius: f32,
+    duration: Duration,
+    color: Color,
+) {
+    commands.spawn((
+        PbrBundle {
+            mesh: mesh.0.clone(),
+            material: materials.add(StandardMaterial {
+                base_color: color,
+                alpha_mode: AlphaMode::Add,
+                unlit: true,
+                ..default()
+            }),
+            transform: Transform::from_translation(position).with_scale(Vec3::ZERO),
+            ..default()
+        },
+        NotShadowCaster,
+        NotShadowReceiver,
+        Explosion {
+            timer: Timer::new(duration, TimerMode::Once),
+            radius,
+        },
+    ));
+}
+
+fn animate_explosions(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut explosions: Query<(Entity, &mut Explosion, &mut Transform, &Handle<StandardMaterial>)>,
+) {
+    for (entity, mut explosion, mut transform, material) in &mut explosions {
+        explosion.timer.tick(time.delta());
+
+        if explosion.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        let t = explosion.timer.fraction();
+        transform.scale = Vec3::splat(explosion.radius * t.sqrt());
+
+        if let Some(material) = materials.get_mut(material) {
+            material.base_color.set_alpha(1. - t);
+        }
+    }
+}
+
+/// A light that lights up the side of the field that was scored on, and then dims
+#[derive(Component)]
+struct GoalFlash(Timer);
+
+fn goal_effects(
+    mut commands: Commands,
+    mut goals: EventReader<GoalScored>,
+    mesh: Res<ExplosionMesh>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for goal in goals.read() {
+        let color = get_color_from_team(goal.team);
+
+        spawn_explosion(
+            &mut commands,
+            &mesh,
+            &mut materials,
+            goal.position,
+            GOAL_EXPLOSION_RADIUS,
+            GOAL_EXPLOSION_DURATION,
+            color,
+        );
+
+        commands.spawn((
+            PointLightBundle {
+                point_light: PointLight {
+                    color,
+                    intensity: GOAL_FLASH_INTENSITY,
+                    range: 10_000.,
+                    ..default()
+                },
+                // a bit in front of the net, so the light reaches the field instead of only the inside of the net
+                transform: Transform::from_translation(goal.position * Vec3::new(1., 1., 0.8) + Vec3::Y * 500.),
+                ..default()
+            },
+            GoalFlash(Timer::new(GOAL_FLASH_DURATION, TimerMode::Once)),
+        ));
+    }
+}
+
+fn fade_goal_flashes(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut flashes: Query<(Entity, &mut GoalFlash, &mut PointLight)>,
+) {
+    for (entity, mut flash, mut light) in &mut flashes {
+        flash.0.tick(time.delta());
+
+        if flash.0.finished() {
+            commands.entity(entity).despawn_recursive();
+        } else {
+            light.intensity = GOAL_FLASH_INTENSITY * (1. - flash.0.fraction()).powi(2);
+        }
+    }
+}
+
+/// The time until a demolished car respawns, shown over the spot it was destroyed at
+#[derive(Component)]
+struct DemoCountdown(u32);
+
+/// Blow up cars as they're demolished, and keep a countdown around until they respawn
+fn update_demos(
+    mut commands: Commands,
+    states: Res<GameStates>,
+    mesh: Res<ExplosionMesh>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    countdowns: Query<(Entity, &DemoCountdown)>,
+    mut demoed: Local<HashSet<u32>>,
+) {
+    let now_demoed = states
+        .current
+        .cars
+        .iter()
+        .filter(|car| car.state.is_demoed)
+        .map(|car| car.id)
+        .collect::<HashSet<_>>();
+
+    for car in states.current.cars.iter() {
+        if !car.state.is_demoed || demoed.contains(&car.id) {
+            continue;
+        }
+
+        spawn_explosion(
+            &mut commands,
+            &mesh,
+            &mut materials,
+            car.state.pos.to_bevy(),
+            DEMO_EXPLOSION_RADIUS,
+            DEMO_EXPLOSION_DURATION,
+            Color::from(css::ORANGE_RED),
+        );
+
+        commands.spawn((
+            TextBundle {
+                // not shown until it has been placed on the screen
+                visibility: Visibility::Hidden,
+                ..TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 30.,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    ..default()
+                })
+            },
+            DemoCountdown(car.id),
+        ));
+    }
+
+    // cars that respawned or are gone entirely, like after switching to another simulation
+    for (entity, countdown) in &countdowns {
+        if !now_demoed.contains(&countdown.0) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    *demoed = now_demoed;
+}
+
+/// Outline demolished cars where they were destroyed
+///
+/// The respawn position isn't in the game state, because the simulation only picks one of the
+/// spawn points at random once the timer runs out, so the demolition spot is the only one known.
+fn draw_demo_ghosts(states: Res<GameStates>, options: Res<Options>, mut gizmos: Gizmos) {
+    if !options.demo_ghosts {
+        return;
+    }
+
+    for car in states.current.cars.iter().filter(|car| car.state.is_demoed) {
+        let rotation = car.state.rot_mat.to_bevy();
+        let offset = rotation * car.config.hitbox_pos_offset.to_bevy();
+
+        gizmos.cuboid(
+            Transform::from_translation(car.state.pos.to_bevy() + offset)
+                .with_rotation(rotation)
+                .with_scale(car.config.hitbox_size.to_bevy()),
+            get_color_from_team(car.team).with_alpha(0.4),
+        );
+    }
+}
+
+fn place_demo_countdowns(
+    states: Res<GameStates>,
+    options: Res<Options>,
+    world_to_ui: WorldToUi,
+    mut countdowns: Query<(&DemoCountdown, &Node, &mut Text, &mut Style, &mut Visibility)>,
+) {
+    for (countdown, node, mut text, mut style, mut visibility) in &mut countdowns {
+        let screen_position = options
+            .demo_ghosts
+            .then(|| states.current.cars.iter().find(|car| car.id == countdown.0))
+            .flatten()
+            .and_then(|car| {
+                text.sections[0].value = format!("{:.1}", car.state.demo_respawn_timer);
+                world_to_ui.project(car.state.pos.to_bevy())
+            });
+
+        let Some(screen_position) = screen_position else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        let size = node.size();
+        style.left = Val::Px(screen_position.x - size.x / 2.);
+        style.top = Val::Px(screen_position.y - size.y / 2.);
+        *visibility = Visibility::Inherited;
+    }
+}
+
+pub struct EffectsPlugin;
+
+impl Plugin for EffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_explosions)
+            .add_systems(
+                Update,
+                (
+                    update_demos,
+                    animate_explosions,
+                    goal_effects.run_if(on_event::<GoalScored>()),
+                    fade_goal_flashes,
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                (draw_demo_ghosts, place_demo_countdowns.before(UiSystem::Layout)).in_set(GameStateApplied),
+            );
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{
+    rocketsim::{GameMode, Team},
+    udp::{GameStates, ToBevyVec},
+};
+use bevy::{math::Vec3A, prelude::*};
+
+const SOCCAR_BALL_RADIUS: f32 = 91.25;
+const SNOWDAY_PUCK_RADIUS: f32 = 114.25;
+const HOOPS_BALL_RADIUS: f32 = 96.3831;
+/// The ball has to be completely behind this to be in the net
+const SOCCAR_GOAL_THRESHOLD_Y: f32 = 5124.25;
+/// The ball has to be below the rim of the hoop
+const HOOPS_GOAL_THRESHOLD_Z: f32 = 270.;
+/// How far the center of the hoops' nets are from the center of the field
+const HOOPS_NET_Y: f32 = 2770.;
+const HOOPS_NET_RADIUS: f32 = 716.;
+/// The nets are a bit narrower along the length of the field
+const HOOPS_NET_SCALE_Y: f32 = 0.9;
+
+/// The ball went into a net
+#[derive(Event, Clone, Copy, Debug)]
+pub struct GoalScored {
+    /// The team that scored, not the team whose net the ball went into
+    pub team: Team,
+    /// Where the ball was when the goal was detected
+    pub position: Vec3,
+}
+
+/// The team that the ball is scoring for at `pos`, if any
+fn scoring_team(game_mode: GameMode, pos: Vec3A) -> Option<Team> {
+    let in_net = match game_mode {
+        GameMode::Soccar | GameMode::HeatSeeker => pos.y.abs() > SOCCAR_GOAL_THRESHOLD_Y + SOCCAR_BALL_RADIUS,
+        GameMode::Snowday => pos.y.abs() > SOCCAR_GOAL_THRESHOLD_Y + SNOWDAY_PUCK_RADIUS,
+        GameMode::Hoops => {
+            let y = (pos.y.abs() - HOOPS_NET_Y) * HOOPS_NET_SCALE_Y;
+            let radius = HOOPS_NET_RADIUS - HOOPS_BALL_RADIUS;
+
+            pos.z < HOOPS_GOAL_THRESHOLD_Z && pos.x * pos.x + y * y < radius * radius
+        }
+        GameMode::TheVoid => false,
+    };
+
+    // blue defends the net at negative y
+    in_net.then_some(if pos.y > 0. { Team::Blue } else { Team::Orange })
+}
+
+/// Only fires once per goal, when the ball first goes in
+fn detect_goals(
+    states: Res<GameStates>,
+    game_mode: Res<GameMode>,
+    mut goals: EventWriter<GoalScored>,
+    mut in_net: Local<bool>,
+) {
+    let ball = &states.current.ball;
+    let scoring_team = scoring_team(*game_mode, ball.pos);
+
+    if let (Some(team), false) = (scoring_team, *in_net) {
+        info!("{team:?} scored on tick {}", states.current.tick_count);
+
+        goals.send(GoalScored {
+            team,
+            position: ball.pos.to_bevy(),
+        });
+    }
+
+    *in_net = scoring_team.is_some();
+}
+
+pub struct GoalsPlugin;
+
+impl Plugin for GoalsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<GoalScored>().add_systems(Update, detect_goals);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn soccar_goals() {
+        assert_eq!(scoring_team(GameMode::Soccar, Vec3A::new(0., 5300., 100.)), Some(Team::Blue));
+        assert_eq!(
+            scoring_team(GameMode::Soccar, Vec3A::new(0., -5300., 100.)),
+            Some(Team::Orange)
+        );
+        assert_eq!(scoring_team(GameMode::Soccar, Vec3A::new(0., 0., 93.15)), None);
+    }
+
+    #[test]
+    fn ball_has_to_be_completely_over_the_line() {
+        // past the threshold, but not by the whole ball
+        assert_eq!(scoring_team(GameMode::Soccar, Vec3A::new(0., 5200., 100.)), None);
+        assert_eq!(scoring_team(GameMode::HeatSeeker, Vec3A::new(0., 5200., 100.)), None);
+
+        // the puck is bigger than the ball
+        assert_eq!(scoring_team(GameMode::Soccar, Vec3A::new(0., 5230., 100.)), Some(Team::Blue));
+        assert_eq!(scoring_team(GameMode::Snowday, Vec3A::new(0., 5230., 100.)), None);
+    }
+
+    #[test]
+    fn hoops_goals() {
+        assert_eq!(scoring_team(GameMode::Hoops, Vec3A::new(0., 2770., 100.)), Some(Team::Blue));
+        assert_eq!(
+            scoring_team(GameMode::Hoops, Vec3A::new(0., -2770., 100.)),
+            Some(Team::Orange)
+        );
+
+        // above the rim
+        assert_eq!(scoring_team(GameMode::Hoops, Vec3A::new(0., 2770., 300.)), None);
+        // outside of the net
+        assert_eq!(scoring_team(GameMode::Hoops, Vec3A::new(650., 2770., 100.)), None);
+        // just as far from the center, but the net is narrower along the length of the field
+        assert_eq!(
+            scoring_team(GameMode::Hoops, Vec3A::new(0., 2770. + 650., 100.)),
+            Some(Team::Blue)
+        );
+    }
+
+    #[test]
+    fn no_goals_in_the_void() {
+        assert_eq!(scoring_team(GameMode::TheVoid, Vec3A::new(0., 10000., 100.)), None);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{
+    peers::Peers,
+    replay::Recorder,
+    stream::Transport,
+    udp::{self, PeerUpdate, SendableUdp, UdpUpdate},
+    ServerConfig,
+};
+use std::io;
+
+/// Record packets without opening a window or loading any assets, until every simulation has quit
+pub fn run(config: &ServerConfig, transport: &Transport, mut recorder: Recorder) -> io::Result<()> {
+    if !recorder.is_recording() {
+        recorder
+            .start(Recorder::default_path())
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to start recording due to: {e}")))?;
+    }
+
+    if let Some(path) = recorder.path() {
+        println!("Recording packets to {}", path.display());
+    }
+
+    let (outgoing, updates) = udp::connect(config, transport)?;
+    let mut peers = Peers::default();
+
+    for PeerUpdate { peer, update } in updates {
+        match update {
+            UdpUpdate::Exit => {
+                peers.remove(peer);
+
+                if peers.is_empty() {
+                    break;
+                }
+            }
+            UdpUpdate::Disconnected => peers.remove(peer),
+            UdpUpdate::BadPacket(e) => println!("Dropped a bad packet from {peer}: {e}"),
+            UdpUpdate::Handshake(handshake) => {
+                peers.seen(peer).handshake = Some(handshake);
+                drop(outgoing.send(SendableUdp::Handshake(peer, Some(handshake))));
+            }
+            UdpUpdate::Connection => {
+                peers.seen(peer);
+                drop(outgoing.send(SendableUdp::Handshake(peer, None)));
+
+                // there's nobody to pause the simulation, so let it run at full speed
+                drop(outgoing.send(SendableUdp::Paused(false)));
+                drop(outgoing.send(SendableUdp::Speed(1.)));
+            }
+            UdpUpdate::State(state, received) => {
+                let info = peers.seen(peer);
+                info.update(&state);
+                let compatible = info.is_compatible();
+
+                // only one simulation can be recorded, which is the first one like in the visualizer
+                if compatible && peers.is_active(peer) {
+                    recorder.record_state(received, &state);
+                }
+            }
+            UdpUpdate::Render(render_message, received) => {
+                let compatible = peers.seen(peer).is_compatible();
+
+                if compatible && peers.is_active(peer) {
+                    recorder.record_render(received, &render_message);
+                }
+            }
+            UdpUpdate::Speed(_) | UdpUpdate::Paused(_) => {
+                peers.seen(peer);
+            }
+        }
+
+        if let Some((peer, _)) = peers.take_changed() {
+            drop(outgoing.send(SendableUdp::Target(peer)));
+        }
+
+        if !recorder.is_recording() {
+            println!("Stopped recording because the replay file couldn't be written to");
+            return Ok(());
+        }
+    }
+
+    recorder.stop();
+
+    if let Some(path) = recorder.path() {
+        println!("Saved recording to {}", path.display());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,265 @@
+use crate::{rocketsim::Team, settings::options::Options, udp::NewGameState};
+use bevy::{
+    math::Vec3A,
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::ImageSampler,
+    },
+    utils::HashMap,
+};
+use std::time::Duration;
+
+const CELL_SIZE: f32 = 128.;
+const HALF_WIDTH: f32 = 4096.;
+/// Long enough to include the inside of the soccar goals
+const HALF_LENGTH: f32 = 6016.;
+const COLUMNS: usize = (HALF_WIDTH * 2. / CELL_SIZE) as usize;
+const ROWS: usize = (HALF_LENGTH * 2. / CELL_SIZE) as usize;
+/// Just above the floor so it doesn't flicker
+const OVERLAY_HEIGHT: f32 = 2.;
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often something was in each cell of the field
+struct Grid(Box<[u32]>);
+
+impl Default for Grid {
+    #[inline]
+    fn default() -> Self {
+        Self(vec![0; COLUMNS * ROWS].into_boxed_slice())
+    }
+}
+
+impl Grid {
+    /// Rows go along the length of the field, starting at blue's side
+    fn add(&mut self, pos: Vec3A) {
+        let column = ((pos.x + HALF_WIDTH) / CELL_SIZE).floor();
+        let row = ((pos.y + HALF_LENGTH) / CELL_SIZE).floor();
+
+        if (0. ..COLUMNS as f32).contains(&column) && (0. ..ROWS as f32).contains(&row) {
+            self.0[row as usize * COLUMNS + column as usize] += 1;
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HeatmapSource {
+    Ball,
+    Team(Team),
+    Car(u32),
+}
+
+impl HeatmapSource {
+    pub fn name(self) -> String {
+        match self {
+            Self::Ball => String::from("Ball"),
+            Self::Team(team) => format!("{team:?} team"),
+            Self::Car(id) => format!("Car {id}"),
+        }
+    }
+}
+
+/// Positions from every received game state, kept for the ball and each car so the source can be switched at any time
+#[derive(Resource)]
+pub struct Heatmap {
+    pub source: HeatmapSource,
+    num_samples: u64,
+    ball: Grid,
+    cars: HashMap<u32, (Team, Grid)>,
+}
+
+impl Default for Heatmap {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            source: HeatmapSource::Ball,
+            num_samples: 0,
+            ball: Grid::default(),
+            cars: HashMap::default(),
+        }
+    }
+}
+
+impl Heatmap {
+    pub fn reset(&mut self) {
+        *self = Self {
+            source: self.source,
+            ..default()
+        };
+    }
+
+    #[inline]
+    pub const fn num_samples(&self) -> u64 {
+        self.num_samples
+    }
+
+    pub fn car_ids(&self) -> Vec<u32> {
+        let mut ids = self.cars.keys().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// The count of every cell for the current source
+    fn counts(&self) -> Vec<u32> {
+        let mut counts = vec![0; COLUMNS * ROWS];
+
+        let grids: Box<dyn Iterator<Item = &Grid>> = match self.source {
+            HeatmapSource::Ball => Box::new(std::iter::once(&self.ball)),
+            HeatmapSource::Team(team) => Box::new(
+                self.cars
+                    .values()
+                    .filter(move |(car_team, _)| *car_team == team)
+                    .map(|(_, grid)| grid),
+            ),
+            HeatmapSource::Car(id) => Box::new(self.cars.get(&id).map(|(_, grid)| grid).into_iter()),
+        };
+
+        for grid in grids {
+            for (count, cell) in counts.iter_mut().zip(grid.0.iter()) {
+                *count += cell;
+            }
+        }
+
+        counts
+    }
+}
+
+fn record_positions(mut new_game_states: EventReader<NewGameState>, mut heatmap: ResMut<Heatmap>) {
+    for NewGameState { state, .. } in new_game_states.read() {
+        heatmap.num_samples += 1;
+        heatmap.ball.add(state.ball.pos);
+
+        for car in state.cars.iter().filter(|car| !car.state.is_demoed) {
+            let (team, grid) = heatmap.cars.entry(car.id).or_default();
+            *team = car.team;
+            grid.add(car.state.pos);
+        }
+    }
+}
+
+/// Transparent where nothing has been, going from blue to red for the cells that were visited the most
+fn heat_color(heat: f32) -> [u8; 4] {
+    if heat <= 0. {
+        return [0; 4];
+    }
+
+    Color::hsl(240. * (1. - heat), 1., 0.5)
+        .to_srgba()
+        .with_alpha(0.3 + 0.7 * heat)
+        .to_u8_array()
+}
+
+#[derive(Component)]
+struct HeatmapOverlay;
+
+#[derive(Resource)]
+struct HeatmapTexture {
+    image: Handle<Image>,
+    material: Handle<StandardMaterial>,
+}
+
+fn setup_heatmap_overlay(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: COLUMNS as u32,
+            height: ROWS as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0; 4],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.sampler = ImageSampler::linear();
+
+    let image = images.add(image);
+    let material = materials.add(StandardMaterial {
+        base_color_texture: Some(image.clone()),
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+
+    // the plane's texture goes along +x and +z, which are the simulation's +x and +y
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(Plane3d::default().mesh().size(HALF_WIDTH * 2., HALF_LENGTH * 2.)),
+            material: material.clone(),
+            transform: Transform::from_xyz(0., OVERLAY_HEIGHT, 0.),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        NotShadowCaster,
+        NotShadowReceiver,
+        HeatmapOverlay,
+    ));
+
+    commands.insert_resource(HeatmapTexture { image, material });
+}
+
+fn update_heatmap_overlay(
+    time: Res<Time>,
+    options: Res<Options>,
+    heatmap: Res<Heatmap>,
+    texture: Res<HeatmapTexture>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut overlay: Query<&mut Visibility, With<HeatmapOverlay>>,
+    mut refresh: Local<Option<Timer>>,
+) {
+    let visibility = if options.heatmap {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+
+    for mut overlay_visibility in &mut overlay {
+        if *overlay_visibility != visibility {
+            *overlay_visibility = visibility;
+        }
+    }
+
+    if !options.heatmap {
+        return;
+    }
+
+    // redrawing the texture every frame would be wasteful, since it changes very little between frames
+    let refresh = refresh.get_or_insert_with(|| Timer::new(REFRESH_INTERVAL, TimerMode::Repeating));
+    if !refresh.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    if let Some(material) = materials.get_mut(&texture.material) {
+        material.base_color = Color::WHITE.with_alpha(options.heatmap_opacity);
+    }
+
+    let Some(image) = images.get_mut(&texture.image) else {
+        return;
+    };
+
+    let counts = heatmap.counts();
+    // on a log scale, so places that were only passed through still show up during long runs
+    let max = counts.iter().copied().max().unwrap_or_default().max(1) as f32;
+    let max = max.ln_1p();
+
+    for (pixel, count) in image.data.chunks_exact_mut(4).zip(counts) {
+        pixel.copy_from_slice(&heat_color((count as f32).ln_1p() / max));
+    }
+}
+
+pub struct HeatmapPlugin;
+
+impl Plugin for HeatmapPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Heatmap::default())
+            .add_systems(Startup, setup_heatmap_overlay)
+            .add_systems(Update, (record_positions, update_heatmap_overlay).chain());
+    }
+}
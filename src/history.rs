@@ -0,0 +1,229 @@
+use crate::rocketsim::GameState;
+use bevy::prelude::*;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// The most recently received game states, so the view can be rewound while the simulation keeps running
+#[derive(Resource)]
+pub struct StateHistory {
+    states: VecDeque<(Instant, GameState)>,
+    max_age: Duration,
+    /// The number of states that have been dropped from the front of the buffer
+    num_dropped: u64,
+    /// Absolute index of the state being viewed, or `None` when following the live feed
+    viewing: Option<u64>,
+}
+
+impl Default for StateHistory {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            states: VecDeque::new(),
+            max_age: Duration::from_secs(30),
+            num_dropped: 0,
+            viewing: None,
+        }
+    }
+}
+
+impl StateHistory {
+    pub fn push(&mut self, received: Instant, state: GameState) {
+        self.states.push_back((received, state));
+
+        while let Some((oldest, _)) = self.states.front() {
+            if received.saturating_duration_since(*oldest) <= self.max_age {
+                break;
+            }
+
+            self.states.pop_front();
+            self.num_dropped += 1;
+        }
+    }
+
+    /// Forget every state, e.g. when a different simulation is shown
+    pub fn clear(&mut self) {
+        self.num_dropped += self.states.len() as u64;
+        self.states.clear();
+        self.viewing = None;
+    }
+
+    #[inline]
+    pub fn set_max_age(&mut self, max_age: Duration) {
+        self.max_age = max_age;
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// The newest state that was received, even while an older one is being viewed
+    #[inline]
+    pub fn latest(&self) -> Option<&GameState> {
+        self.states.back().map(|(_, state)| state)
+    }
+
+    /// Freeze the view on the latest state
+    pub fn pause_view(&mut self) {
+        if !self.states.is_empty() {
+            self.viewing = Some(self.num_dropped + self.states.len() as u64 - 1);
+        }
+    }
+
+    #[inline]
+    pub fn go_live(&mut self) {
+        self.viewing = None;
+    }
+
+    /// Index into the buffer of the state being viewed
+    pub fn view_index(&self) -> Option<usize> {
+        let viewing = self.viewing?;
+        // if the viewed state has aged out of the buffer, show the oldest one remaining
+        Some(viewing.saturating_sub(self.num_dropped) as usize)
+    }
+
+    pub fn set_view_index(&mut self, index: usize) {
+        if !self.states.is_empty() {
+            self.viewing = Some(self.num_dropped + index.min(self.states.len() - 1) as u64);
+        }
+    }
+
+    pub fn viewed_state(&self) -> Option<&GameState> {
+        self.view_index()
+            .and_then(|index| self.states.get(index))
+            .map(|(_, state)| state)
+    }
+
+    /// The states received at most `max_age` before the one being viewed, oldest first
+    pub fn recent(&self, max_age: Duration) -> impl Iterator<Item = &GameState> + Clone {
+        let end = self
+            .view_index()
+            .map_or(self.states.len(), |index| (index + 1).min(self.states.len()));
+        let start = end
+            .checked_sub(1)
+            .and_then(|index| self.states.get(index))
+            .map_or(0, |(newest, _)| {
+                // states are received in order, so the ones that are too old are all at the front
+                self.states
+                    .partition_point(|(received, _)| newest.saturating_duration_since(*received) > max_age)
+            });
+
+        self.states.range(start..end).map(|(_, state)| state)
+    }
+
+    /// How far the viewed state is behind the latest received state
+    pub fn time_behind_live(&self) -> Duration {
+        let (Some(index), Some((latest, _))) = (self.view_index(), self.states.back()) else {
+            return Duration::ZERO;
+        };
+
+        self.states
+            .get(index)
+            .map(|(viewed, _)| latest.saturating_duration_since(*viewed))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A history with one state per second, where each state's tick count is its index
+    fn history(num_states: u64, max_age: Duration) -> (StateHistory, Instant) {
+        let start = Instant::now();
+        let mut history = StateHistory::default();
+        history.set_max_age(max_age);
+
+        for tick_count in 0..num_states {
+            history.push(start + Duration::from_secs(tick_count), GameState { tick_count, ..default() });
+        }
+
+        (history, start)
+    }
+
+    #[test]
+    fn drops_states_older_than_max_age() {
+        let (history, _) = history(10, Duration::from_secs(3));
+
+        assert_eq!(history.len(), 4);
+        assert_eq!(history.num_dropped, 6);
+    }
+
+    #[test]
+    fn paused_view_stays_on_the_same_state() {
+        let (mut history, start) = history(5, Duration::from_secs(3));
+        history.pause_view();
+        assert_eq!(history.viewed_state().map(|state| state.tick_count), Some(4));
+
+        history.push(start + Duration::from_secs(5), GameState::default());
+        history.push(start + Duration::from_secs(6), GameState::default());
+        assert_eq!(history.view_index(), Some(1));
+        assert_eq!(history.viewed_state().map(|state| state.tick_count), Some(4));
+    }
+
+    #[test]
+    fn aged_out_view_shows_the_oldest_state() {
+        let (mut history, start) = history(2, Duration::from_secs(1));
+        history.set_view_index(0);
+
+        for seconds in 2..5 {
+            history.push(start + Duration::from_secs(seconds), GameState::default());
+        }
+
+        assert_eq!(history.view_index(), Some(0));
+    }
+
+    #[test]
+    fn view_index_is_clamped() {
+        let (mut history, _) = history(3, Duration::from_secs(30));
+        history.set_view_index(10);
+
+        assert_eq!(history.view_index(), Some(2));
+
+        history.go_live();
+        assert_eq!(history.view_index(), None);
+        assert!(history.viewed_state().is_none());
+    }
+
+    #[test]
+    fn clear_goes_live() {
+        let (mut history, _) = history(3, Duration::from_secs(30));
+        history.pause_view();
+        history.clear();
+
+        assert!(history.is_empty());
+        assert_eq!(history.view_index(), None);
+
+        // pausing an empty history does nothing
+        history.pause_view();
+        assert_eq!(history.view_index(), None);
+    }
+
+    #[test]
+    fn recent_ends_at_the_viewed_state() {
+        let (mut history, _) = history(10, Duration::from_secs(30));
+        history.set_view_index(5);
+
+        let ticks = history
+            .recent(Duration::from_secs(2))
+            .map(|state| state.tick_count)
+            .collect::<Vec<_>>();
+        assert_eq!(ticks, [3, 4, 5]);
+    }
+
+    #[test]
+    fn time_behind_live() {
+        let (mut history, _) = history(10, Duration::from_secs(30));
+        assert_eq!(history.time_behind_live(), Duration::ZERO);
+
+        history.set_view_index(6);
+        assert_eq!(history.time_behind_live(), Duration::from_secs(3));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{
+    camera::PrimaryCamera,
+    rocketsim::{CarControls, Team},
+    settings::options::Options,
+    udp::{focused_car_id, get_color_from_team, GameStates},
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Align2, Color32, FontId, Painter, Rect, Sense, Stroke},
+    EguiContexts,
+};
+
+const WIDGET_SIZE: egui::Vec2 = egui::vec2(200., 64.);
+const GAP: f32 = 8.;
+const THROTTLE_WIDTH: f32 = 12.;
+const STICK_DOT_RADIUS: f32 = 6.;
+const BAR_HEIGHT: f32 = 8.;
+const LABEL_WIDTH: f32 = 30.;
+const BACKGROUND: Color32 = Color32::from_gray(40);
+const INACTIVE: Color32 = Color32::from_gray(120);
+
+fn team_color(team: Team) -> Color32 {
+    let [r, g, b, _] = get_color_from_team(team).to_srgba().to_u8_array();
+    Color32::from_rgb(r, g, b)
+}
+
+/// Fills `rect` from its middle towards `value`, which goes from -1 to 1, with up and right being positive
+fn centered_bar(painter: &Painter, rect: Rect, value: f32, vertical: bool, color: Color32) {
+    painter.rect_filled(rect, 2., BACKGROUND);
+
+    let value = value.clamp(-1., 1.);
+    let center = rect.center();
+    let fill = if vertical {
+        Rect::from_two_pos(
+            egui::pos2(rect.left(), center.y),
+            egui::pos2(rect.right(), center.y - value * rect.height() / 2.),
+        )
+    } else {
+        Rect::from_two_pos(
+            egui::pos2(center.x, rect.top()),
+            egui::pos2(center.x + value * rect.width() / 2., rect.bottom()),
+        )
+    };
+
+    painter.rect_filled(fill, 0., color);
+}
+
+fn labeled_bar(painter: &Painter, left: f32, right: f32, center_y: f32, label: &str, value: f32, color: Color32) {
+    painter.text(
+        egui::pos2(left, center_y),
+        Align2::LEFT_CENTER,
+        label,
+        FontId::proportional(11.),
+        INACTIVE,
+    );
+
+    let bar = Rect::from_min_max(
+        egui::pos2(left + LABEL_WIDTH, center_y - BAR_HEIGHT / 2.),
+        egui::pos2(right, center_y + BAR_HEIGHT / 2.),
+    );
+    centered_bar(painter, bar, value, false, color);
+}
+
+fn button(painter: &Painter, rect: Rect, label: &str, pressed: bool, color: Color32) {
+    let text_color = if pressed {
+        painter.rect_filled(rect, 4., color);
+        Color32::WHITE
+    } else {
+        painter.rect_stroke(rect, 4., Stroke::new(1., INACTIVE));
+        INACTIVE
+    };
+
+    painter.text(
+        rect.center(),
+        Align2::CENTER_CENTER,
+        label,
+        FontId::proportional(11.),
+        text_color,
+    );
+}
+
+/// A stick for steer and pitch, a bar for the throttle, bars for yaw and roll, and the buttons
+fn controls_widget(ui: &mut egui::Ui, controls: &CarControls, color: Color32) {
+    let (response, painter) = ui.allocate_painter(WIDGET_SIZE, Sense::hover());
+    let rect = response.rect;
+    let height = rect.height();
+
+    // drawn like a controller's stick, which is pushed forward to pitch the nose down
+    let stick_radius = height / 2.;
+    let stick_center = rect.left_center() + egui::vec2(stick_radius, 0.);
+    let stick = egui::vec2(controls.steer.clamp(-1., 1.), controls.pitch.clamp(-1., 1.));
+
+    painter.circle_filled(stick_center, stick_radius, BACKGROUND);
+    painter.circle_stroke(stick_center, stick_radius, Stroke::new(1., INACTIVE));
+    painter.circle_filled(
+        stick_center + stick * (stick_radius - STICK_DOT_RADIUS),
+        STICK_DOT_RADIUS,
+        color,
+    );
+
+    let throttle_left = rect.left() + height + GAP;
+    let throttle = Rect::from_min_size(egui::pos2(throttle_left, rect.top()), egui::vec2(THROTTLE_WIDTH, height));
+    centered_bar(&painter, throttle, controls.throttle, true, color);
+
+    let left = throttle_left + THROTTLE_WIDTH + GAP;
+    let right = rect.right();
+    labeled_bar(&painter, left, right, rect.top() + BAR_HEIGHT, "Yaw", controls.yaw, color);
+    labeled_bar(
+        &painter,
+        left,
+        right,
+        rect.top() + BAR_HEIGHT * 3.,
+        "Roll",
+        controls.roll,
+        color,
+    );
+
+    let buttons_top = rect.top() + BAR_HEIGHT * 5.;
+    let button_width = (right - left - GAP) / 3.;
+    let buttons = [
+        ("Boost", controls.boost),
+        ("Jump", controls.jump),
+        ("Drift", controls.handbrake),
+    ];
+
+    for (i, (label, pressed)) in buttons.into_iter().enumerate() {
+        let button_left = left + i as f32 * (button_width + GAP / 2.);
+        let button_rect = Rect::from_min_max(
+            egui::pos2(button_left, buttons_top),
+            egui::pos2(button_left + button_width, rect.bottom()),
+        );
+        button(&painter, button_rect, label, pressed, color);
+    }
+}
+
+fn draw_input_overlay(
+    mut contexts: EguiContexts,
+    options: Res<Options>,
+    states: Res<GameStates>,
+    camera: Query<&PrimaryCamera>,
+) {
+    if !options.input_overlay && !options.input_grid {
+        return;
+    }
+
+    let ctx = contexts.ctx_mut();
+    let cars = &states.current.cars;
+
+    if options.input_overlay {
+        let focused = camera
+            .get_single()
+            .ok()
+            .and_then(|camera| focused_car_id(camera, cars))
+            .and_then(|id| cars.iter().find(|car| car.id == id));
+
+        if let Some(car) = focused {
+            egui::Area::new(egui::Id::new("input_overlay"))
+                .anchor(Align2::CENTER_BOTTOM, [0., -10.])
+                .interactable(false)
+                .show(ctx, |ui| {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        controls_widget(ui, &car.state.last_controls, team_color(car.team));
+                    });
+                });
+        }
+    }
+
+    if options.input_grid && !cars.is_empty() {
+        let mut cars = cars.iter().collect::<Vec<_>>();
+        cars.sort_unstable_by_key(|car| car.id);
+
+        egui::Window::new("Inputs").resizable(false).show(ctx, |ui| {
+            egui::Grid::new("inputs").spacing([GAP * 2., GAP]).show(ui, |ui| {
+                for (i, car) in cars.into_iter().enumerate() {
+                    ui.label(format!("Car {}", car.id));
+                    controls_widget(ui, &car.state.last_controls, team_color(car.team));
+
+                    if i % 2 == 1 {
+                        ui.end_row();
+                    }
+                }
+            });
+        });
+    }
+}
+
+pub struct InputOverlayPlugin;
+
+impl Plugin for InputOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, draw_input_overlay);
+    }
+}
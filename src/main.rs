@@ -3,22 +3,48 @@
 mod assets;
 mod bytes;
 mod camera;
+mod cli;
+mod effects;
+mod goals;
+mod headless;
+mod heatmap;
+mod history;
+mod inputs;
 mod mesh;
 mod morton;
+mod particles;
+mod peers;
+mod prediction;
 mod renderer;
+mod replay;
 mod rocketsim;
 mod settings;
 mod spectator;
+mod stream;
+mod telemetry;
+mod touches;
+mod trails;
 mod udp;
 
 use bevy::{
     diagnostic::LogDiagnosticsPlugin,
     prelude::*,
     render::texture::{ImageAddressMode, ImageSamplerDescriptor},
-    window::PresentMode,
+    window::{PresentMode, WindowResolution},
 };
-use settings::gui;
-use std::env;
+use clap::Parser;
+use cli::Cli;
+use replay::Recorder;
+use settings::{
+    gui,
+    options::{Options, StartingCamera},
+};
+use std::{
+    io::{self, ErrorKind},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs},
+    process::ExitCode,
+};
+use udp::PacketSource;
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash, Default, States)]
 enum GameLoadState {
@@ -30,27 +56,74 @@ enum GameLoadState {
     None,
 }
 
-#[derive(Resource)]
-pub struct ServerPort {
+pub struct ServerConfig {
     primary_port: u16,
     secondary_port: u16,
+    /// Where to send packets until a simulation has been picked
+    target_host: String,
+    /// Which address to listen on, defaults to every address of the same IP version as the target
+    bind_host: Option<String>,
+}
+
+impl ServerConfig {
+    fn resolve(host: &str, port: u16) -> io::Result<SocketAddr> {
+        (host, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("Couldn't resolve {host}")))
+    }
+
+    pub fn target_addr(&self) -> io::Result<SocketAddr> {
+        Self::resolve(&self.target_host, self.primary_port)
+    }
+
+    pub fn bind_addr(&self) -> io::Result<SocketAddr> {
+        if let Some(host) = &self.bind_host {
+            return Self::resolve(host, self.secondary_port);
+        }
+
+        // an IPv4 socket can't send to an IPv6 address or the other way around
+        let ip = match self.target_addr() {
+            Ok(SocketAddr::V6(_)) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            _ => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        };
+
+        Ok(SocketAddr::new(ip, self.secondary_port))
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            println!("{e}");
+            ExitCode::FAILURE
+        }
+    }
 }
 
-fn main() {
-    let mut args = env::args();
+fn run(cli: Cli) -> io::Result<()> {
+    let mut recorder = Recorder::default();
+    if let Some(path) = &cli.record {
+        let path = path.clone().unwrap_or_else(Recorder::default_path);
+
+        if let Err(e) = recorder.start(path) {
+            println!("Failed to start recording due to: {e}");
+        }
+    }
 
-    // read the first argument and treat it as the port to connect to (u16)
-    let primary_port = args.nth(1).and_then(|s| s.parse::<u16>().ok()).unwrap_or(34254);
-    // read the second argument and treat it as the port to bind the UDP socket to (u16)
-    let secondary_port = args.next().and_then(|s| s.parse::<u16>().ok()).unwrap_or(45243);
+    if cli.headless {
+        return headless::run(&cli.server_config(), &cli.transport(), recorder);
+    }
 
-    assets::uncook().unwrap();
+    Options::set_file_path(cli.settings.clone());
+    assets::uncook(cli.assets_path.as_deref())?;
+    let packet_source = PacketSource::open(cli.replay.as_deref(), &cli.server_config(), &cli.transport())?;
 
     App::new()
-        .insert_resource(ServerPort {
-            primary_port,
-            secondary_port,
-        })
+        .insert_resource(packet_source)
+        .insert_resource(recorder)
+        .insert_resource(StartingCamera(cli.camera))
         .add_plugins((
             DefaultPlugins
                 .set(ImagePlugin {
@@ -65,6 +138,7 @@ fn main() {
                     primary_window: Some(Window {
                         title: "RLViser-rs".into(),
                         present_mode: PresentMode::AutoNoVsync,
+                        resolution: WindowResolution::new(cli.width, cli.height),
                         ..default()
                     }),
                     ..default()
@@ -78,7 +152,16 @@ fn main() {
             mesh::FieldLoaderPlugin,
             udp::RocketSimPlugin,
             assets::AssetsLoaderPlugin,
+            trails::TrailsPlugin,
+            prediction::BallPredictionPlugin,
+            effects::EffectsPlugin,
+            goals::GoalsPlugin,
+            particles::ParticlesPlugin,
+            touches::TouchesPlugin,
         ))
+        .add_plugins((inputs::InputOverlayPlugin, telemetry::TelemetryPlugin, heatmap::HeatmapPlugin))
         .init_state::<GameLoadState>()
         .run();
+
+    Ok(())
 }
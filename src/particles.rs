@@ -0,0 +1,254 @@
+use crate::{
+    settings::options::Options,
+    udp::{Car, GameStateApplied, GameStates, ToBevyVec},
+};
+use bevy::{
+    color::palettes::css,
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+    utils::HashMap,
+};
+use std::time::Duration;
+
+/// Particles per second coming out of a boosting car
+const BOOST_RATE: f32 = 120.;
+const BOOST_SPEED: f32 = 900.;
+const BOOST_LIFETIME: Duration = Duration::from_millis(250);
+const BOOST_SIZE: f32 = 14.;
+/// Particles per second left behind by a supersonic car
+const SUPERSONIC_RATE: f32 = 60.;
+const SUPERSONIC_LIFETIME: Duration = Duration::from_millis(400);
+const SUPERSONIC_SIZE: f32 = 8.;
+/// How long it takes for the supersonic trail to reach its full size
+const SUPERSONIC_RAMP_SECS: f32 = 0.5;
+const PUFF_PARTICLES: usize = 16;
+const PUFF_SPEED: f32 = 300.;
+const PUFF_LIFETIME: Duration = Duration::from_millis(350);
+const PUFF_SIZE: f32 = 18.;
+/// Skipping ahead, like scrubbing through the history, shouldn't spawn a burst of particles
+const MAX_EMIT_SECS: f32 = 0.1;
+
+#[derive(Resource)]
+struct ParticleAssets {
+    mesh: Handle<Mesh>,
+    boost: Handle<StandardMaterial>,
+    supersonic: Handle<StandardMaterial>,
+    puff: Handle<StandardMaterial>,
+}
+
+/// Flies in a straight line while it shrinks away
+#[derive(Component)]
+struct Particle {
+    velocity: Vec3,
+    size: f32,
+    timer: Timer,
+}
+
+fn setup_particles(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut glowing = |color: Srgba| {
+        materials.add(StandardMaterial {
+            base_color: Color::Srgba(color),
+            alpha_mode: AlphaMode::Add,
+            unlit: true,
+            ..default()
+        })
+    };
+
+    commands.insert_resource(ParticleAssets {
+        mesh: meshes.add(Sphere::new(1.).mesh().ico(1).unwrap()),
+        boost: glowing(css::ORANGE),
+        supersonic: glowing(css::LIGHT_CYAN.with_alpha(0.6)),
+        puff: glowing(css::WHITE_SMOKE.with_alpha(0.5)),
+    });
+}
+
+/// A cheap xorshift, since the particles only need to look random
+fn random(seed: &mut u32) -> f32 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 17;
+    *seed ^= *seed << 5;
+
+    // between -1 and 1
+    *seed as f32 / u32::MAX as f32 * 2. - 1.
+}
+
+fn random_vec(seed: &mut u32) -> Vec3 {
+    Vec3::new(random(seed), random(seed), random(seed))
+}
+
+fn spawn_particle(
+    commands: &mut Commands,
+    mesh: &Handle<Mesh>,
+    material: &Handle<StandardMaterial>,
+    position: Vec3,
+    velocity: Vec3,
+    size: f32,
+    lifetime: Duration,
+) {
+    commands.spawn((
+        PbrBundle {
+            mesh: mesh.clone(),
+            material: material.clone(),
+            transform: Transform::from_translation(position).with_scale(Vec3::splat(size)),
+            ..default()
+        },
+        NotShadowCaster,
+        NotShadowReceiver,
+        Particle {
+            velocity,
+            size,
+            timer: Timer::new(lifetime, TimerMode::Once),
+        },
+    ));
+}
+
+#[derive(Default)]
+struct Emitter {
+    /// Fractions of particles that are carried over to the next frame, so the rate doesn't depend on the frame rate
+    boost: f32,
+    supersonic: f32,
+    has_jumped: bool,
+    has_flipped: bool,
+}
+
+/// How many particles to spawn this frame
+fn take_particles(carry: &mut f32, rate: f32, dt: f32) -> usize {
+    *carry += rate * dt;
+    let num = carry.floor();
+    *carry -= num;
+    num as usize
+}
+
+fn emit_car_particles(
+    mut commands: Commands,
+    options: Res<Options>,
+    states: Res<GameStates>,
+    assets: Res<ParticleAssets>,
+    cars: Query<(&Transform, &Car)>,
+    mut emitters: Local<HashMap<u32, Emitter>>,
+    mut seed: Local<u32>,
+    mut last_tick_count: Local<u64>,
+) {
+    // emitted for the simulated time that passed, so nothing comes out while paused or rewound
+    let tick_count = states.current.tick_count;
+    let ticks = tick_count.checked_sub(*last_tick_count).unwrap_or_default();
+    *last_tick_count = tick_count;
+
+    if ticks == 0 {
+        return;
+    }
+
+    // xorshift gets stuck at 0
+    if *seed == 0 {
+        *seed = 0x9E37_79B9;
+    }
+
+    let seed = &mut *seed;
+
+    let dt = (ticks as f32 / states.current.tick_rate).min(MAX_EMIT_SECS);
+    emitters.retain(|id, _| states.current.cars.iter().any(|car| car.id == *id));
+
+    for (transform, car) in &cars {
+        let Some(info) = states.current.cars.iter().find(|info| info.id == car.id()) else {
+            continue;
+        };
+
+        let state = &info.state;
+        let emitter = emitters.entry(car.id()).or_default();
+        let has_jumped = state.has_jumped && !emitter.has_jumped;
+        let has_flipped = state.has_flipped && !emitter.has_flipped;
+        emitter.has_jumped = state.has_jumped;
+        emitter.has_flipped = state.has_flipped;
+
+        if !options.particles || state.is_demoed {
+            continue;
+        }
+
+        let forward = transform.rotation * Vec3::X;
+        let up = transform.rotation * Vec3::Y;
+        let velocity = state.vel.to_bevy();
+        let hitbox = info.config.hitbox_size.to_bevy();
+        let back =
+            transform.translation + transform.rotation * info.config.hitbox_pos_offset.to_bevy() - forward * hitbox.x / 2.;
+
+        if state.last_controls.boost && state.boost > f32::EPSILON {
+            for _ in 0..take_particles(&mut emitter.boost, BOOST_RATE, dt) {
+                spawn_particle(
+                    &mut commands,
+                    &assets.mesh,
+                    &assets.boost,
+                    back + random_vec(seed) * 5.,
+                    velocity - forward * BOOST_SPEED + random_vec(seed) * 100.,
+                    BOOST_SIZE,
+                    BOOST_LIFETIME,
+                );
+            }
+        }
+
+        if state.is_supersonic {
+            let ramp = (state.supersonic_time / SUPERSONIC_RAMP_SECS).clamp(0.2, 1.);
+
+            for _ in 0..take_particles(&mut emitter.supersonic, SUPERSONIC_RATE, dt) {
+                // left behind in the air instead of following the car
+                spawn_particle(
+                    &mut commands,
+                    &assets.mesh,
+                    &assets.supersonic,
+                    back + random_vec(seed) * hitbox.z / 2.,
+                    random_vec(seed) * 20.,
+                    SUPERSONIC_SIZE * ramp,
+                    SUPERSONIC_LIFETIME,
+                );
+            }
+        }
+
+        if has_jumped || has_flipped {
+            let bottom = transform.translation - up * hitbox.y / 2.;
+
+            for _ in 0..PUFF_PARTICLES {
+                // spreads out around the bottom of the car
+                let direction = random_vec(seed).reject_from(up).normalize_or_zero();
+
+                spawn_particle(
+                    &mut commands,
+                    &assets.mesh,
+                    &assets.puff,
+                    bottom,
+                    velocity * 0.5 + direction * PUFF_SPEED,
+                    PUFF_SIZE,
+                    PUFF_LIFETIME,
+                );
+            }
+        }
+    }
+}
+
+fn update_particles(mut commands: Commands, time: Res<Time>, mut particles: Query<(Entity, &mut Particle, &mut Transform)>) {
+    let dt = time.delta_seconds();
+
+    for (entity, mut particle, mut transform) in &mut particles {
+        particle.timer.tick(time.delta());
+
+        if particle.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        transform.translation += particle.velocity * dt;
+        transform.scale = Vec3::splat(particle.size * particle.timer.fraction_remaining());
+    }
+}
+
+pub struct ParticlesPlugin;
+
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_particles)
+            .add_systems(Update, update_particles)
+            .add_systems(PostUpdate, emit_car_particles.in_set(GameStateApplied));
+    }
+}
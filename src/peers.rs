@@ -0,0 +1,160 @@
+use crate::{
+    bytes::DecodeError,
+    rocketsim::{GameMode, GameState},
+    udp::Handshake,
+};
+use ahash::HashMap;
+use bevy::prelude::*;
+use std::{
+    fmt, mem,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+/// Peers that haven't sent anything for this long are forgotten, unless they're being displayed
+pub const PEER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where a stream of packets comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Peer {
+    Addr(SocketAddr),
+    /// A connection over a Unix domain socket, numbered in the order they connected
+    Local(u32),
+    Replay,
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Addr(addr) => addr.fmt(f),
+            Self::Local(id) => write!(f, "Local #{id}"),
+            Self::Replay => f.write_str("Replay"),
+        }
+    }
+}
+
+pub struct PeerInfo {
+    pub last_seen: Instant,
+    pub tick_count: u64,
+    pub game_mode: GameMode,
+    pub num_cars: usize,
+    /// `None` for older clients that connect without a handshake
+    pub handshake: Option<Handshake>,
+    /// Packets that couldn't be decoded and were dropped
+    pub num_bad_packets: u64,
+    pub last_error: Option<DecodeError>,
+}
+
+impl PeerInfo {
+    fn new() -> Self {
+        Self {
+            last_seen: Instant::now(),
+            tick_count: 0,
+            game_mode: GameMode::default(),
+            num_cars: 0,
+            handshake: None,
+            num_bad_packets: 0,
+            last_error: None,
+        }
+    }
+
+    /// Older clients that connect without a handshake are assumed to be compatible
+    #[inline]
+    pub fn is_compatible(&self) -> bool {
+        self.handshake.map_or(true, |handshake| handshake.is_compatible())
+    }
+
+    pub fn update(&mut self, state: &GameState) {
+        self.tick_count = state.tick_count;
+        self.game_mode = state.game_mode;
+        self.num_cars = state.cars.len();
+    }
+}
+
+/// Every simulation that is sending packets, and which one of them is being displayed
+#[derive(Resource, Default)]
+pub struct Peers {
+    peers: HashMap<Peer, PeerInfo>,
+    active: Option<Peer>,
+    /// Set when the displayed peer changes, until the change has been handled
+    changed: bool,
+    /// Whether the change replaced another peer, whose renders and history have to be cleared
+    replaced: bool,
+    /// Whether the displayed peer hasn't sent anything for longer than the timeout
+    active_timed_out: bool,
+}
+
+impl Peers {
+    /// Marks the peer as alive, and displays it if nothing else is being displayed
+    pub fn seen(&mut self, peer: Peer) -> &mut PeerInfo {
+        if self.active.is_none() {
+            // nothing else was displayed, but the simulation still has to be told that it's the target now
+            self.active = Some(peer);
+            self.changed = true;
+        }
+
+        let info = self.peers.entry(peer).or_insert_with(PeerInfo::new);
+        info.last_seen = Instant::now();
+        info
+    }
+
+    /// Forget the peer, displaying another one instead if it was the active peer
+    pub fn remove(&mut self, peer: Peer) {
+        self.peers.remove(&peer);
+
+        if self.active == Some(peer) {
+            self.active = self.peers.keys().min().copied();
+            self.changed = true;
+            self.replaced = self.active.is_some();
+        }
+    }
+
+    /// Returns if the displayed peer just timed out, which stays selected but won't remove what it rendered anymore
+    pub fn remove_stale(&mut self) -> bool {
+        let active = self.active;
+        self.peers
+            .retain(|peer, info| Some(*peer) == active || info.last_seen.elapsed() < PEER_TIMEOUT);
+
+        let timed_out = active
+            .and_then(|peer| self.peers.get(&peer))
+            .is_some_and(|info| info.last_seen.elapsed() >= PEER_TIMEOUT);
+
+        !mem::replace(&mut self.active_timed_out, timed_out) && timed_out
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    #[inline]
+    pub fn is_active(&self, peer: Peer) -> bool {
+        self.active == Some(peer)
+    }
+
+    pub fn select(&mut self, peer: Peer) {
+        if self.active != Some(peer) && self.peers.contains_key(&peer) {
+            self.replaced = self.active.is_some();
+            self.active = Some(peer);
+            self.changed = true;
+        }
+    }
+
+    /// Returns the newly displayed peer if it changed since the last call, and whether it replaced another peer
+    pub fn take_changed(&mut self) -> Option<(Peer, bool)> {
+        if !self.changed {
+            return None;
+        }
+
+        self.changed = false;
+        let replaced = mem::take(&mut self.replaced);
+        self.active.map(|peer| (peer, replaced))
+    }
+
+    /// Sorted by peer so the order is stable
+    pub fn iter(&self) -> impl Iterator<Item = (Peer, &PeerInfo)> {
+        let mut peers = self.peers.iter().map(|(peer, info)| (*peer, info)).collect::<Vec<_>>();
+        peers.sort_unstable_by_key(|(peer, _)| *peer);
+        peers.into_iter()
+    }
+}
@@ -0,0 +1,290 @@
+use crate::{
+    rocketsim::{BallState, GameMode},
+    settings::options::Options,
+    udp::{GameStates, ToBevyVec},
+};
+use bevy::{color::palettes::css, math::Vec3A, prelude::*};
+
+const GRAVITY: Vec3A = Vec3A::new(0., 0., -650.);
+/// Fraction of the ball's velocity that's lost every second
+const DRAG: f32 = 0.03;
+const MAX_SPEED: f32 = 6000.;
+const MAX_ANG_SPEED: f32 = 6.;
+const RESTITUTION: f32 = 0.6;
+const FRICTION: f32 = 0.35;
+const TICK_RATE: f32 = 120.;
+
+/// A flat surface of the arena, that the ball stays on the side of that `normal` points to
+#[derive(Clone, Copy)]
+struct Plane {
+    normal: Vec3A,
+    distance: f32,
+}
+
+impl Plane {
+    fn new(normal: Vec3A, point: Vec3A) -> Self {
+        let normal = normal.normalize();
+
+        Self {
+            normal,
+            distance: normal.dot(point),
+        }
+    }
+
+    #[inline]
+    fn signed_distance(self, point: Vec3A) -> f32 {
+        self.normal.dot(point) - self.distance
+    }
+}
+
+/// The arena as flat planes, so the curved ramps between the floor and walls are only approximated
+struct Arena {
+    ball_radius: f32,
+    planes: Vec<Plane>,
+    /// Half the width and the height of the goal mouths, which the back walls have a hole for
+    goal_size: Option<(f32, f32)>,
+    /// How far behind the back walls the back of the goals are
+    goal_depth: f32,
+    back_wall: f32,
+}
+
+impl Arena {
+    fn new(game_mode: GameMode) -> Option<Self> {
+        let (ball_radius, half_width, half_length, height, corner, goal_size, goal_depth) = match game_mode {
+            GameMode::Soccar => (91.25, 4096., 5120., 2044., Some(8064.), Some((892.755, 642.775)), 880.),
+            GameMode::Hoops => (96.3831, 2966.67, 3581., 1820., None, None, 0.),
+            GameMode::TheVoid => (91.25, f32::INFINITY, f32::INFINITY, f32::INFINITY, None, None, 0.),
+            // the ball in these modes doesn't fly like a normal ball
+            GameMode::HeatSeeker | GameMode::Snowday => return None,
+        };
+
+        let mut planes = Vec::new();
+
+        if height.is_finite() {
+            planes.extend([
+                Plane::new(Vec3A::Z, Vec3A::ZERO),
+                Plane::new(Vec3A::NEG_Z, Vec3A::new(0., 0., height)),
+                Plane::new(Vec3A::X, Vec3A::new(-half_width, 0., 0.)),
+                Plane::new(Vec3A::NEG_X, Vec3A::new(half_width, 0., 0.)),
+            ]);
+        }
+
+        if let Some(corner) = corner {
+            for (x, y) in [(1., 1.), (1., -1.), (-1., 1.), (-1., -1.)] {
+                planes.push(Plane::new(
+                    Vec3A::new(-x, -y, 0.),
+                    Vec3A::new(x * corner / 2., y * corner / 2., 0.),
+                ));
+            }
+        }
+
+        Some(Self {
+            ball_radius,
+            planes,
+            goal_size,
+            goal_depth,
+            back_wall: half_length,
+        })
+    }
+
+    /// The back walls, which are further back inside the goal mouths
+    fn back_walls(&self, pos: Vec3A) -> [Plane; 2] {
+        let in_goal_mouth = self.goal_size.is_some_and(|(half_width, height)| {
+            pos.x.abs() < half_width - self.ball_radius && pos.z < height - self.ball_radius
+        });
+
+        let back_wall = if in_goal_mouth || pos.y.abs() > self.back_wall {
+            self.back_wall + self.goal_depth
+        } else {
+            self.back_wall
+        };
+
+        [
+            Plane::new(Vec3A::Y, Vec3A::new(0., -back_wall, 0.)),
+            Plane::new(Vec3A::NEG_Y, Vec3A::new(0., back_wall, 0.)),
+        ]
+    }
+
+    /// Bounce the ball off the first surface it's touching, returning the normal of that surface
+    fn collide(&self, ball: &mut BallState) -> Option<Vec3A> {
+        let back_walls = self.back_wall.is_finite().then(|| self.back_walls(ball.pos));
+
+        for plane in self.planes.iter().chain(back_walls.iter().flatten()) {
+            let penetration = self.ball_radius - plane.signed_distance(ball.pos);
+            let normal_speed = ball.vel.dot(plane.normal);
+
+            if penetration <= 0. || normal_speed >= 0. {
+                continue;
+            }
+
+            ball.pos += plane.normal * penetration;
+
+            // the velocity of the ball's surface where it touches the plane
+            let tangent_vel = ball.vel - plane.normal * normal_speed;
+            let slip = tangent_vel + ball.ang_vel.cross(-plane.normal * self.ball_radius);
+
+            let normal_impulse = -(1. + RESTITUTION) * normal_speed;
+            let slip_speed = slip.length();
+            let friction = if slip_speed > 0. {
+                slip * -(FRICTION * normal_impulse / slip_speed).min(1.)
+            } else {
+                Vec3A::ZERO
+            };
+
+            ball.vel += plane.normal * normal_impulse + friction;
+            // friction leaves the ball rolling along the surface
+            ball.ang_vel = plane.normal.cross(ball.vel - plane.normal * ball.vel.dot(plane.normal)) / self.ball_radius;
+
+            return Some(plane.normal);
+        }
+
+        None
+    }
+}
+
+/// Where the ball is going to be, and where it's going to bounce
+#[derive(Default)]
+struct Prediction {
+    path: Vec<Vec3A>,
+    /// Where the ball touches a surface, and the normal of that surface
+    bounces: Vec<(Vec3A, Vec3A)>,
+}
+
+impl Prediction {
+    fn simulate(arena: &Arena, mut ball: BallState, seconds: f32) -> Self {
+        let dt = 1. / TICK_RATE;
+        let num_ticks = (seconds * TICK_RATE) as usize;
+
+        let mut prediction = Self::default();
+        prediction.path.push(ball.pos);
+
+        for _ in 0..num_ticks {
+            ball.vel += GRAVITY * dt;
+            ball.vel *= (1. - DRAG).powf(dt);
+            ball.vel = ball.vel.clamp_length_max(MAX_SPEED);
+            ball.ang_vel = ball.ang_vel.clamp_length_max(MAX_ANG_SPEED);
+            ball.pos += ball.vel * dt;
+
+            if let Some(normal) = arena.collide(&mut ball) {
+                prediction.bounces.push((ball.pos - normal * arena.ball_radius, normal));
+            }
+
+            prediction.path.push(ball.pos);
+        }
+
+        prediction
+    }
+}
+
+fn draw_ball_prediction(options: Res<Options>, states: Res<GameStates>, game_mode: Res<GameMode>, mut gizmos: Gizmos) {
+    let Some(arena) = Arena::new(*game_mode) else {
+        return;
+    };
+
+    // the newest state, which is ahead of the ball on screen when packets are interpolated
+    let prediction = Prediction::simulate(&arena, states.next.ball, options.prediction_seconds);
+
+    gizmos.linestrip(prediction.path.into_iter().map(ToBevyVec::to_bevy), css::YELLOW);
+
+    for (contact, normal) in prediction.bounces {
+        if let Ok(normal) = Dir3::new(normal.to_bevy()) {
+            gizmos.circle(contact.to_bevy(), normal, arena.ball_radius, css::ORANGE);
+        }
+    }
+}
+
+pub struct BallPredictionPlugin;
+
+impl Plugin for BallPredictionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            draw_ball_prediction.run_if(|options: Res<Options>| options.ball_prediction),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ball(pos: Vec3A, vel: Vec3A) -> BallState {
+        BallState { pos, vel, ..default() }
+    }
+
+    #[test]
+    fn bounces_off_the_floor() {
+        let arena = Arena::new(GameMode::Soccar).unwrap();
+        let mut ball = ball(Vec3A::new(0., 0., arena.ball_radius - 1.), Vec3A::new(0., 0., -1000.));
+
+        assert_eq!(arena.collide(&mut ball), Some(Vec3A::Z));
+        assert_eq!(ball.pos.z, arena.ball_radius);
+        assert!(ball.vel.abs_diff_eq(Vec3A::new(0., 0., 1000. * RESTITUTION), 1e-3));
+        assert_eq!(ball.ang_vel, Vec3A::ZERO);
+    }
+
+    #[test]
+    fn friction_makes_the_ball_roll() {
+        let arena = Arena::new(GameMode::Soccar).unwrap();
+        let mut ball = ball(Vec3A::new(0., 0., arena.ball_radius - 1.), Vec3A::new(1000., 0., -1000.));
+
+        assert_eq!(arena.collide(&mut ball), Some(Vec3A::Z));
+
+        // the impulse along the floor is limited by friction
+        let normal_impulse = (1. + RESTITUTION) * 1000.;
+        let forward_speed = 1000. - FRICTION * normal_impulse;
+        assert!(ball.vel.abs_diff_eq(Vec3A::new(forward_speed, 0., 1000. * RESTITUTION), 1e-3));
+        assert!(ball
+            .ang_vel
+            .abs_diff_eq(Vec3A::new(0., forward_speed / arena.ball_radius, 0.), 1e-3));
+    }
+
+    #[test]
+    fn moving_away_from_a_surface_doesnt_bounce() {
+        let arena = Arena::new(GameMode::Soccar).unwrap();
+        let mut ball = ball(Vec3A::new(0., 0., arena.ball_radius - 1.), Vec3A::new(0., 0., 500.));
+
+        assert_eq!(arena.collide(&mut ball), None);
+    }
+
+    #[test]
+    fn goal_mouths_are_open() {
+        let arena = Arena::new(GameMode::Soccar).unwrap();
+
+        let mut into_goal = ball(Vec3A::new(0., 5100., 300.), Vec3A::new(0., 2000., 0.));
+        assert_eq!(arena.collide(&mut into_goal), None);
+
+        let mut into_wall = ball(Vec3A::new(2000., 5100., 300.), Vec3A::new(0., 2000., 0.));
+        assert_eq!(arena.collide(&mut into_wall), Some(Vec3A::NEG_Y));
+        assert!(into_wall.vel.y < 0.);
+    }
+
+    #[test]
+    fn dropped_ball_stays_above_the_floor() {
+        let arena = Arena::new(GameMode::Soccar).unwrap();
+        let prediction = Prediction::simulate(&arena, ball(Vec3A::new(0., 0., 500.), Vec3A::ZERO), 3.);
+
+        assert_eq!(prediction.path.len(), 3 * TICK_RATE as usize + 1);
+        assert!(!prediction.bounces.is_empty());
+        assert!(prediction
+            .bounces
+            .iter()
+            .all(|(contact, normal)| *normal == Vec3A::Z && contact.z.abs() < 1e-3));
+        assert!(prediction.path.iter().all(|pos| pos.z >= arena.ball_radius - 1e-3));
+    }
+
+    #[test]
+    fn nothing_to_bounce_off_in_the_void() {
+        let arena = Arena::new(GameMode::TheVoid).unwrap();
+        let prediction = Prediction::simulate(&arena, ball(Vec3A::new(0., 0., 500.), Vec3A::ZERO), 3.);
+
+        assert!(prediction.bounces.is_empty());
+        assert!(prediction.path.last().unwrap().z < 0.);
+    }
+
+    #[test]
+    fn unpredictable_modes_have_no_arena() {
+        assert!(Arena::new(GameMode::HeatSeeker).is_none());
+        assert!(Arena::new(GameMode::Snowday).is_none());
+    }
+}
@@ -1,5 +1,24 @@
-use crate::udp::ToBevyVec;
-use bevy::{prelude::*, utils::HashMap};
+use crate::{
+    camera::WorldToUi,
+    udp::{Ball, Car, GameStateApplied, ToBevyMat, ToBevyVec},
+};
+use bevy::{
+    ecs::system::SystemParam,
+    math::Mat3A,
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+    render::{mesh::PrimitiveTopology, render_asset::RenderAssetUsages},
+    transform::TransformSystem,
+    ui::UiSystem,
+    utils::{HashMap, HashSet},
+};
+use std::{
+    f32::consts::TAU,
+    time::{Duration, Instant},
+};
+
+/// How many line segments a full circle is drawn with
+const CIRCLE_SEGMENTS: u32 = 32;
 
 #[derive(Clone, Copy, Debug)]
 pub struct CustomColor {
@@ -22,38 +41,805 @@ impl CustomColor {
     }
 }
 
+/// What the positions in a render are relative to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Anchor {
+    World,
+    /// The ball's position, without its spin
+    Ball,
+    /// The car's position, and its rotation if `local` is set
+    Car {
+        id: u32,
+        local: bool,
+    },
+}
+
 #[derive(Clone, Debug)]
 pub enum Render {
-    Line2D { start: Vec2, end: Vec2, color: CustomColor },
-    Line { start: Vec3, end: Vec3, color: CustomColor },
-    LineStrip { positions: Vec<Vec3>, color: CustomColor },
+    Line2D {
+        start: Vec2,
+        end: Vec2,
+        color: CustomColor,
+    },
+    Line {
+        start: Vec3,
+        end: Vec3,
+        color: CustomColor,
+    },
+    LineStrip {
+        positions: Vec<Vec3>,
+        color: CustomColor,
+    },
+    Sphere {
+        center: Vec3,
+        radius: f32,
+        color: CustomColor,
+    },
+    /// An axis-aligned box
+    Box {
+        center: Vec3,
+        half_extents: Vec3,
+        color: CustomColor,
+    },
+    /// A box rotated the same way as a car, with `half_extents` along its forward, right and up axes
+    OrientedBox {
+        center: Vec3,
+        half_extents: Vec3,
+        rotation: Mat3A,
+        color: CustomColor,
+    },
+    Circle {
+        center: Vec3,
+        normal: Vec3,
+        radius: f32,
+        color: CustomColor,
+    },
+    Ring {
+        center: Vec3,
+        normal: Vec3,
+        inner_radius: f32,
+        outer_radius: f32,
+        color: CustomColor,
+    },
+    /// Part of a circle around `center`, from `start` rotating `angle` radians around `normal`
+    Arc {
+        center: Vec3,
+        normal: Vec3,
+        start: Vec3,
+        angle: f32,
+        color: CustomColor,
+    },
+    Arrow {
+        start: Vec3,
+        end: Vec3,
+        color: CustomColor,
+    },
+    /// Text at `position` pixels from the top left of the window, `size` pixels tall
+    Text2D {
+        position: Vec2,
+        size: f32,
+        color: CustomColor,
+        text: String,
+    },
+    /// Text centered above `position` in the world, `size` pixels tall no matter how far away it is
+    Text3D {
+        position: Vec3,
+        size: f32,
+        color: CustomColor,
+        text: String,
+    },
+    /// Any other render with its positions relative to `anchor`, following it between packets
+    Anchored {
+        anchor: Anchor,
+        render: Box<Render>,
+    },
+    /// Filled triangles from every three positions, tinted by `vertex_colors` if there's one for each position
+    Triangles {
+        positions: Vec<Vec3>,
+        color: CustomColor,
+        vertex_colors: Option<Vec<CustomColor>>,
+    },
+    /// A filled quad through the corners in order
+    Quad {
+        corners: [Vec3; 4],
+        color: CustomColor,
+    },
+}
+
+impl Render {
+    /// The anchor of the render and what's anchored to it
+    pub fn with_anchor(&self) -> (Anchor, &Self) {
+        match self {
+            Self::Anchored { anchor, render } => (*anchor, render),
+            render => (Anchor::World, render),
+        }
+    }
+}
+
+/// How long a render group stays on screen if it isn't replaced or removed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lifetime {
+    Seconds(f32),
+    /// Counted from the tick of the latest game state
+    Ticks(u32),
 }
 
 #[derive(Clone, Debug)]
 pub enum RenderMessage {
-    AddRender(i32, Vec<Render>),
+    AddRender(i32, Vec<Render>, Option<Lifetime>),
     RemoveRender(i32),
+    ClearAll,
+    /// Label a group in the menu, which is kept until every group is cleared
+    NameGroup(i32, GroupInfo),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GroupInfo {
+    pub name: String,
+    /// Groups in the same category can be shown and hidden together, like every group from one bot
+    pub category: String,
+}
+
+/// Only show one group or category
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Solo {
+    Group(i32),
+    Category(String),
+}
+
+#[derive(Clone, Copy)]
+enum Expiry {
+    At(Instant),
+    Tick(u64),
 }
 
 #[derive(Resource, Default)]
 pub struct RenderGroups {
     pub groups: HashMap<i32, Vec<Render>>,
+    /// When the groups that were given a lifetime should be removed
+    expiries: HashMap<i32, Expiry>,
+    info: HashMap<i32, GroupInfo>,
+    /// Hidden from the menu, which is kept when the groups are cleared
+    hidden_groups: HashSet<i32>,
+    hidden_categories: HashSet<String>,
+    solo: Option<Solo>,
 }
 
-fn render_gizmos(mut renders: ResMut<RenderGroups>, mut gizmos: Gizmos) {
-    for (_, renders) in renders.groups.iter_mut() {
-        for render in renders.iter() {
-            match render {
-                Render::Line2D { start, end, color } => {
-                    gizmos.line_2d(*start, *end, *color);
+impl RenderGroups {
+    /// Only flag the groups as changed if `change` returns that it changed something,
+    /// since the render text and meshes are rebuilt every time they're flagged
+    pub fn update(this: &mut ResMut<Self>, change: impl FnOnce(&mut Self) -> bool) {
+        if change(this.bypass_change_detection()) {
+            this.set_changed();
+        }
+    }
+
+    /// `tick_count` is the tick of the latest game state
+    pub fn apply(&mut self, message: RenderMessage, tick_count: u64) {
+        match message {
+            RenderMessage::AddRender(group_id, renders, lifetime) => {
+                let expiry = match lifetime {
+                    // lifetimes too long to represent never expire
+                    Some(Lifetime::Seconds(secs)) => Duration::try_from_secs_f32(secs.max(0.))
+                        .ok()
+                        .and_then(|lifetime| Instant::now().checked_add(lifetime))
+                        .map(Expiry::At),
+                    Some(Lifetime::Ticks(ticks)) => Some(Expiry::Tick(tick_count + u64::from(ticks))),
+                    None => None,
+                };
+
+                match expiry {
+                    Some(expiry) => self.expiries.insert(group_id, expiry),
+                    None => self.expiries.remove(&group_id),
+                };
+
+                self.groups.insert(group_id, renders);
+            }
+            RenderMessage::RemoveRender(group_id) => {
+                self.groups.remove(&group_id);
+                self.expiries.remove(&group_id);
+            }
+            RenderMessage::ClearAll => {
+                self.clear();
+            }
+            RenderMessage::NameGroup(group_id, info) => {
+                self.info.insert(group_id, info);
+            }
+        }
+    }
+
+    /// Returns if there was anything to clear
+    pub fn clear(&mut self) -> bool {
+        let had_groups = !self.groups.is_empty();
+
+        self.groups.clear();
+        self.expiries.clear();
+        self.info.clear();
+
+        had_groups
+    }
+
+    #[inline]
+    pub fn info(&self, group_id: i32) -> Option<&GroupInfo> {
+        self.info.get(&group_id)
+    }
+
+    /// Groups without a category are in the `""` category
+    #[inline]
+    pub fn category(&self, group_id: i32) -> &str {
+        self.info(group_id).map_or("", |info| info.category.as_str())
+    }
+
+    #[inline]
+    pub const fn solo(&self) -> Option<&Solo> {
+        self.solo.as_ref()
+    }
+
+    /// Soloing whatever is already soloed turns it off
+    pub fn toggle_solo(&mut self, solo: Solo) {
+        if self.solo.as_ref() == Some(&solo) {
+            self.solo = None;
+        } else {
+            self.solo = Some(solo);
+        }
+    }
+
+    #[inline]
+    pub fn is_group_hidden(&self, group_id: i32) -> bool {
+        self.hidden_groups.contains(&group_id)
+    }
+
+    pub fn set_group_hidden(&mut self, group_id: i32, hidden: bool) {
+        if hidden {
+            self.hidden_groups.insert(group_id);
+        } else {
+            self.hidden_groups.remove(&group_id);
+        }
+    }
+
+    #[inline]
+    pub fn is_category_hidden(&self, category: &str) -> bool {
+        self.hidden_categories.contains(category)
+    }
+
+    pub fn set_category_hidden(&mut self, category: &str, hidden: bool) {
+        if hidden {
+            self.hidden_categories.insert(category.to_string());
+        } else {
+            self.hidden_categories.remove(category);
+        }
+    }
+
+    /// Soloing a group or category shows it even if it was hidden
+    pub fn is_visible(&self, group_id: i32) -> bool {
+        let category = self.category(group_id);
+
+        match &self.solo {
+            Some(Solo::Group(solo_id)) => *solo_id == group_id,
+            Some(Solo::Category(solo_category)) => solo_category == category,
+            None => !self.is_group_hidden(group_id) && !self.is_category_hidden(category),
+        }
+    }
+
+    /// Every render in the groups that are visible
+    pub fn visible(&self) -> impl Iterator<Item = &Render> {
+        self.groups
+            .iter()
+            .filter(|(group_id, _)| self.is_visible(**group_id))
+            .flat_map(|(_, renders)| renders)
+    }
+
+    /// Returns if any group was removed
+    pub fn remove_expired(&mut self, tick_count: u64) -> bool {
+        let now = Instant::now();
+        let expired = self
+            .expiries
+            .iter()
+            .filter(|(_, expiry)| match expiry {
+                Expiry::At(instant) => *instant <= now,
+                Expiry::Tick(tick) => *tick <= tick_count,
+            })
+            .map(|(group_id, _)| *group_id)
+            .collect::<Vec<_>>();
+
+        for group_id in &expired {
+            self.groups.remove(group_id);
+            self.expiries.remove(group_id);
+        }
+
+        !expired.is_empty()
+    }
+}
+
+/// Points along an arc, in the simulation's coordinates
+fn arc_points(center: Vec3, normal: Vec3, start: Vec3, angle: f32) -> impl Iterator<Item = Vec3> {
+    let axis = normal.normalize_or_zero();
+    let offset = start - center;
+    // the start is flattened onto the plane of the arc
+    let offset = offset - axis * offset.dot(axis);
+
+    let num_segments = if axis == Vec3::ZERO {
+        0
+    } else {
+        ((angle.abs() / TAU * CIRCLE_SEGMENTS as f32).ceil() as u32).max(1)
+    };
+
+    (0..=num_segments).map(move |i| center + Quat::from_axis_angle(axis, angle * i as f32 / num_segments as f32) * offset)
+}
+
+/// The transform of an anchor, so that renders relative to it can be drawn in the world
+#[derive(SystemParam)]
+struct AnchorTargets<'w, 's> {
+    ball: Query<'w, 's, &'static Transform, With<Ball>>,
+    cars: Query<'w, 's, (&'static Transform, &'static Car)>,
+}
+
+impl AnchorTargets<'_, '_> {
+    /// `None` if the anchor doesn't exist, like a car that was removed
+    fn resolve(&self, anchor: Anchor) -> Option<Transform> {
+        match anchor {
+            Anchor::World => Some(Transform::IDENTITY),
+            Anchor::Ball => self
+                .ball
+                .get_single()
+                .ok()
+                .map(|ball| Transform::from_translation(ball.translation)),
+            Anchor::Car { id, local } => self.cars.iter().find(|(_, car)| car.id() == id).map(|(car, _)| {
+                if local {
+                    *car
+                } else {
+                    Transform::from_translation(car.translation)
+                }
+            }),
+        }
+    }
+}
+
+/// `transform` places positions that are relative to an anchor into the world
+fn draw_render(gizmos: &mut Gizmos, render: &Render, transform: &Transform) {
+    let point = |position: &Vec3| transform.transform_point(position.to_bevy());
+    let direction = |direction: &Vec3| transform.rotation * direction.to_bevy();
+
+    match render {
+        Render::Line2D { start, end, color } => {
+            gizmos.line_2d(*start, *end, *color);
+        }
+        Render::Line { start, end, color } => {
+            gizmos.line(point(start), point(end), *color);
+        }
+        Render::LineStrip { positions, color } => {
+            gizmos.linestrip(positions.iter().map(point), *color);
+        }
+        Render::Sphere { center, radius, color } => {
+            gizmos.sphere(point(center), transform.rotation, *radius, *color);
+        }
+        Render::Box {
+            center,
+            half_extents,
+            color,
+        } => {
+            gizmos.cuboid(
+                Transform::from_translation(point(center))
+                    .with_rotation(transform.rotation)
+                    .with_scale(half_extents.to_bevy() * 2.),
+                *color,
+            );
+        }
+        Render::OrientedBox {
+            center,
+            half_extents,
+            rotation,
+            color,
+        } => {
+            gizmos.cuboid(
+                Transform::from_translation(point(center))
+                    .with_rotation(transform.rotation * rotation.to_bevy())
+                    .with_scale(half_extents.to_bevy() * 2.),
+                *color,
+            );
+        }
+        Render::Circle {
+            center,
+            normal,
+            radius,
+            color,
+        } => {
+            if let Ok(normal) = Dir3::new(direction(normal)) {
+                gizmos
+                    .circle(point(center), normal, *radius, *color)
+                    .segments(CIRCLE_SEGMENTS as usize);
+            }
+        }
+        Render::Ring {
+            center,
+            normal,
+            inner_radius,
+            outer_radius,
+            color,
+        } => {
+            if let Ok(normal) = Dir3::new(direction(normal)) {
+                for radius in [inner_radius, outer_radius] {
+                    gizmos
+                        .circle(point(center), normal, *radius, *color)
+                        .segments(CIRCLE_SEGMENTS as usize);
                 }
-                Render::Line { start, end, color } => {
-                    gizmos.line(start.to_bevy(), end.to_bevy(), *color);
+            }
+        }
+        Render::Arc {
+            center,
+            normal,
+            start,
+            angle,
+            color,
+        } => {
+            gizmos.linestrip(arc_points(*center, *normal, *start, *angle).map(|pos| point(&pos)), *color);
+        }
+        Render::Arrow { start, end, color } => {
+            gizmos.arrow(point(start), point(end), *color);
+        }
+        // nested anchors are rejected when decoding
+        Render::Anchored { .. } => {}
+        // drawn with UI nodes instead
+        Render::Text2D { .. } | Render::Text3D { .. } => {}
+        // drawn with meshes instead
+        Render::Triangles { .. } | Render::Quad { .. } => {}
+    }
+}
+
+fn render_gizmos(renders: Res<RenderGroups>, anchors: AnchorTargets, mut gizmos: Gizmos) {
+    for render in renders.visible() {
+        let (anchor, render) = render.with_anchor();
+
+        if let Some(transform) = anchors.resolve(anchor) {
+            draw_render(&mut gizmos, render, &transform);
+        }
+    }
+}
+
+/// A text label from a render group, reused while the group still has a text at `index`
+#[derive(Component)]
+struct RenderText {
+    group_id: i32,
+    /// Counted among the texts of the group
+    index: usize,
+    /// What 3D text is anchored to, and its position relative to that
+    world_position: Option<(Anchor, Vec3)>,
+}
+
+/// What a label should show for a text render
+struct TextLabel {
+    world_position: Option<(Anchor, Vec3)>,
+    /// Where 2D text is on the screen
+    left: f32,
+    top: f32,
+    section: TextSection,
+}
+
+impl TextLabel {
+    fn new(render: &Render) -> Option<Self> {
+        let (anchor, render) = render.with_anchor();
+
+        let (left, top, world_position, size, color, text) = match render {
+            Render::Text2D {
+                position,
+                size,
+                color,
+                text,
+            } => (position.x, position.y, None, size, color, text),
+            Render::Text3D {
+                position,
+                size,
+                color,
+                text,
+            } => (0., 0., Some((anchor, position.to_bevy())), size, color, text),
+            _ => return None,
+        };
+
+        Some(Self {
+            world_position,
+            left,
+            top,
+            section: TextSection::new(
+                text.clone(),
+                TextStyle {
+                    font_size: *size,
+                    color: (*color).into(),
+                    ..default()
+                },
+            ),
+        })
+    }
+
+    const fn justify(&self) -> JustifyText {
+        if self.world_position.is_some() {
+            JustifyText::Center
+        } else {
+            JustifyText::Left
+        }
+    }
+
+    /// The label is an empty node at its position on the screen, which the text overflows from
+    ///
+    /// 2D text hangs down and to the right of it, while 3D text is centered above it,
+    /// so 3D text can be placed without knowing how big it is
+    fn style(&self, left: Val, top: Val) -> Style {
+        let (left, top, justify_content, align_items) = if self.world_position.is_some() {
+            (left, top, JustifyContent::Center, AlignItems::FlexEnd)
+        } else {
+            (
+                Val::Px(self.left),
+                Val::Px(self.top),
+                JustifyContent::FlexStart,
+                AlignItems::FlexStart,
+            )
+        };
+
+        Style {
+            position_type: PositionType::Absolute,
+            left,
+            top,
+            width: Val::Px(0.),
+            height: Val::Px(0.),
+            justify_content,
+            align_items,
+            ..default()
+        }
+    }
+
+    fn update_text(self, text: &mut Mut<Text>) {
+        let justify = self.justify();
+        let unchanged = text.justify == justify
+            && text.sections.len() == 1
+            && text.sections[0].value == self.section.value
+            && text.sections[0].style.font_size == self.section.style.font_size
+            && text.sections[0].style.color == self.section.style.color;
+
+        if !unchanged {
+            text.justify = justify;
+            text.sections = vec![self.section];
+        }
+    }
+}
+
+/// Update the text labels whenever the render groups change, reusing the labels that are still around
+fn update_render_text(
+    mut commands: Commands,
+    renders: Res<RenderGroups>,
+    do_rendering: Res<DoRendering>,
+    mut render_texts: Query<(Entity, &mut RenderText, &mut Style, &mut Visibility, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    if !renders.is_changed() && !do_rendering.is_changed() {
+        return;
+    }
+
+    let mut labels = HashMap::<(i32, usize), TextLabel>::default();
+    for (group_id, group) in &renders.groups {
+        // every label is removed while rendering is turned off
+        if !do_rendering.0 || !renders.is_visible(*group_id) {
+            continue;
+        }
+
+        for (index, label) in group.iter().filter_map(TextLabel::new).enumerate() {
+            labels.insert((*group_id, index), label);
+        }
+    }
+
+    for (entity, mut render_text, mut style, mut visibility, children) in &mut render_texts {
+        let Some(label) = labels.remove(&(render_text.group_id, render_text.index)) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        // 3D text stays where it was on the screen until it's placed again
+        let new_style = label.style(style.left, style.top);
+        if *style != new_style {
+            *style = new_style;
+        }
+
+        if label.world_position.is_none() && *visibility != Visibility::Inherited {
+            *visibility = Visibility::Inherited;
+        }
+
+        render_text.world_position = label.world_position;
+
+        if let Some(mut text) = children.first().and_then(|child| texts.get_mut(*child).ok()) {
+            label.update_text(&mut text);
+        }
+    }
+
+    for ((group_id, index), label) in labels {
+        let justify = label.justify();
+
+        commands
+            .spawn((
+                NodeBundle {
+                    style: label.style(Val::Auto, Val::Auto),
+                    // 3D text isn't shown until it has been placed on the screen
+                    visibility: if label.world_position.is_some() {
+                        Visibility::Hidden
+                    } else {
+                        Visibility::Inherited
+                    },
+                    ..default()
+                },
+                RenderText {
+                    group_id,
+                    index,
+                    world_position: label.world_position,
+                },
+            ))
+            .with_children(|parent| {
+                parent.spawn(
+                    TextBundle::from_sections([label.section])
+                        .with_text_justify(justify)
+                        .with_no_wrap()
+                        .with_style(Style {
+                            // overflowing the empty label instead of being squeezed into it
+                            flex_shrink: 0.,
+                            ..default()
+                        }),
+                );
+            });
+    }
+}
+
+/// Move 3D text to where its world position is on the screen
+fn place_render_text(
+    world_to_ui: WorldToUi,
+    anchors: AnchorTargets,
+    mut render_texts: Query<(&RenderText, &mut Style, &mut Visibility)>,
+) {
+    for (render_text, mut style, mut visibility) in &mut render_texts {
+        let Some((anchor, position)) = render_text.world_position else {
+            continue;
+        };
+
+        let Some(screen_position) = anchors
+            .resolve(anchor)
+            .and_then(|transform| world_to_ui.project(transform.transform_point(position)))
+        else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        style.left = Val::Px(screen_position.x);
+        style.top = Val::Px(screen_position.y);
+        *visibility = Visibility::Inherited;
+    }
+}
+
+/// Vertices of a render group's filled shapes, relative to their anchor
+#[derive(Default)]
+struct FilledVertices {
+    positions: Vec<[f32; 3]>,
+    colors: Vec<[f32; 4]>,
+}
+
+impl FilledVertices {
+    fn push(&mut self, position: Vec3, color: CustomColor) {
+        self.positions.push(position.to_bevy().to_array());
+        self.colors.push(LinearRgba::from(Color::from(color)).to_f32_array());
+    }
+
+    fn add(&mut self, render: &Render) {
+        match render {
+            Render::Triangles {
+                positions,
+                color,
+                vertex_colors,
+            } => {
+                let vertex_colors = vertex_colors.as_ref().filter(|colors| colors.len() == positions.len());
+                // leftover positions that don't make up a whole triangle are ignored
+                let num_vertices = positions.len() / 3 * 3;
+
+                for (i, position) in positions.iter().take(num_vertices).enumerate() {
+                    self.push(*position, vertex_colors.map_or(*color, |colors| colors[i]));
                 }
-                Render::LineStrip { positions, color } => {
-                    gizmos.linestrip(positions.iter().copied().map(ToBevyVec::to_bevy), *color);
+            }
+            Render::Quad { corners, color } => {
+                for i in [0, 1, 2, 0, 2, 3] {
+                    self.push(corners[i], *color);
                 }
             }
+            _ => {}
+        }
+    }
+
+    fn into_mesh(self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+        mesh.compute_flat_normals();
+        mesh
+    }
+}
+
+/// The filled shapes of a render group that share an anchor
+#[derive(Component)]
+struct RenderMesh {
+    group_id: i32,
+    anchor: Anchor,
+}
+
+/// Every render mesh is tinted by its vertex colors
+#[derive(Resource)]
+struct RenderMeshMaterial(Handle<StandardMaterial>);
+
+fn setup_render_meshes(mut commands: Commands, mut materials: ResMut<Assets<StandardMaterial>>) {
+    commands.insert_resource(RenderMeshMaterial(materials.add(StandardMaterial {
+        base_color: Color::WHITE,
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        double_sided: true,
+        cull_mode: None,
+        ..default()
+    })));
+}
+
+/// Rebuild the meshes whenever the render groups change, reusing the entities of groups that are still around
+fn update_render_meshes(
+    mut commands: Commands,
+    renders: Res<RenderGroups>,
+    do_rendering: Res<DoRendering>,
+    material: Res<RenderMeshMaterial>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    render_meshes: Query<(Entity, &RenderMesh, &Handle<Mesh>)>,
+) {
+    if !renders.is_changed() && !do_rendering.is_changed() {
+        return;
+    }
+
+    let mut vertices = HashMap::<(i32, Anchor), FilledVertices>::default();
+    for (group_id, group) in &renders.groups {
+        // every mesh is removed while rendering is turned off
+        if !do_rendering.0 || !renders.is_visible(*group_id) {
+            continue;
+        }
+
+        for render in group {
+            let (anchor, render) = render.with_anchor();
+            if matches!(render, Render::Triangles { .. } | Render::Quad { .. }) {
+                vertices.entry((*group_id, anchor)).or_default().add(render);
+            }
+        }
+    }
+
+    for (entity, render_mesh, handle) in &render_meshes {
+        match vertices.remove(&(render_mesh.group_id, render_mesh.anchor)) {
+            Some(vertices) => {
+                meshes.insert(handle, vertices.into_mesh());
+            }
+            // the mesh is freed with the last handle to it
+            None => commands.entity(entity).despawn_recursive(),
+        }
+    }
+
+    for ((group_id, anchor), vertices) in vertices {
+        commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(vertices.into_mesh()),
+                material: material.0.clone(),
+                // not shown until it has been moved to its anchor
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            NotShadowCaster,
+            NotShadowReceiver,
+            RenderMesh { group_id, anchor },
+        ));
+    }
+}
+
+/// Move the meshes to their anchors, hiding the ones whose anchor is gone
+fn place_render_meshes(anchors: AnchorTargets, mut render_meshes: Query<(&RenderMesh, &mut Transform, &mut Visibility)>) {
+    for (render_mesh, mut transform, mut visibility) in &mut render_meshes {
+        match anchors.resolve(render_mesh.anchor) {
+            Some(anchor) => {
+                *transform = anchor;
+                *visibility = Visibility::Inherited;
+            }
+            None => *visibility = Visibility::Hidden,
         }
     }
 }
@@ -67,6 +853,20 @@ impl Plugin for UdpRendererPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(RenderGroups::default())
             .insert_resource(DoRendering(true))
-            .add_systems(Update, render_gizmos.run_if(|do_rendering: Res<DoRendering>| do_rendering.0));
+            .add_systems(Startup, setup_render_meshes)
+            .add_systems(Update, (update_render_text, update_render_meshes))
+            .add_systems(
+                PostUpdate,
+                (
+                    render_gizmos.run_if(|do_rendering: Res<DoRendering>| do_rendering.0),
+                    place_render_text
+                        .run_if(|do_rendering: Res<DoRendering>| do_rendering.0)
+                        .before(UiSystem::Layout),
+                    place_render_meshes
+                        .run_if(|do_rendering: Res<DoRendering>| do_rendering.0)
+                        .before(TransformSystem::TransformPropagate),
+                )
+                    .in_set(GameStateApplied),
+            );
     }
 }
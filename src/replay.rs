@@ -0,0 +1,379 @@
+use crate::{
+    bytes::{FromBytes, ToBytes},
+    peers::Peer,
+    renderer::{RenderGroups, RenderMessage},
+    rocketsim::GameState,
+    settings::options::Options,
+    udp::{PeerUpdate, UdpUpdate},
+};
+use bevy::prelude::*;
+use crossbeam_channel::Sender;
+use std::{
+    fs,
+    io::{self, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Every replay file starts with these bytes, followed by [`REPLAY_VERSION`]
+pub const REPLAY_MAGIC: [u8; 4] = *b"RLVR";
+pub const REPLAY_VERSION: u8 = 1;
+
+const REPLAY_DIR: &str = "replays";
+
+/// Each entry in a replay file is stored as:
+///
+/// `[entry type: u8][receive time in microseconds: u64][payload length: u32][payload]`
+///
+/// The payload is the same `ToBytes` encoding that is sent over the network.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayEntryType {
+    GameState,
+    Render,
+}
+
+impl ReplayEntryType {
+    pub const fn new(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::GameState),
+            1 => Some(Self::Render),
+            _ => None,
+        }
+    }
+}
+
+struct RecordEntry {
+    entry_type: ReplayEntryType,
+    time_micros: u64,
+    payload: Vec<u8>,
+}
+
+/// Appends every received packet to a replay file while active
+#[derive(Resource, Default)]
+pub struct Recorder {
+    sender: Option<Sender<RecordEntry>>,
+    writer: Option<JoinHandle<()>>,
+    path: Option<PathBuf>,
+    start: Option<Instant>,
+}
+
+impl Recorder {
+    /// A new file in the replays folder, named after the current time
+    pub fn default_path() -> PathBuf {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+
+        Path::new(REPLAY_DIR).join(format!("{secs}.rlvr"))
+    }
+
+    pub fn start(&mut self, path: PathBuf) -> io::Result<()> {
+        self.stop();
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut writer = BufWriter::new(fs::File::create(&path)?);
+        writer.write_all(&REPLAY_MAGIC)?;
+        writer.write_all(&[REPLAY_VERSION])?;
+
+        let (tx, rx) = crossbeam_channel::unbounded::<RecordEntry>();
+
+        let handle = thread::spawn(move || {
+            for entry in rx.iter() {
+                let result = writer
+                    .write_all(&[entry.entry_type as u8])
+                    .and_then(|()| writer.write_all(&entry.time_micros.to_le_bytes()))
+                    .and_then(|()| writer.write_all(&(entry.payload.len() as u32).to_le_bytes()))
+                    .and_then(|()| writer.write_all(&entry.payload))
+                    // flush whenever there's nothing else to write, so little is lost if the process is killed
+                    .and_then(|()| if rx.is_empty() { writer.flush() } else { Ok(()) });
+
+                if let Err(e) = result {
+                    error!("Failed to write to replay file due to: {e}");
+                    return;
+                }
+            }
+
+            if let Err(e) = writer.flush() {
+                error!("Failed to flush replay file due to: {e}");
+            }
+        });
+
+        info!("Recording packets to {}", path.display());
+
+        self.sender = Some(tx);
+        self.writer = Some(handle);
+        self.path = Some(path);
+        self.start = Some(Instant::now());
+
+        Ok(())
+    }
+
+    /// Dropping the sender lets the writer thread flush and exit, which is waited on so the file is complete
+    pub fn stop(&mut self) {
+        if self.sender.take().is_some() {
+            if let Some(path) = &self.path {
+                info!("Stopped recording to {}", path.display());
+            }
+        }
+
+        if let Some(writer) = self.writer.take() {
+            drop(writer.join());
+        }
+
+        self.start = None;
+    }
+
+    #[inline]
+    pub const fn is_recording(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    #[inline]
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn record(&mut self, entry_type: ReplayEntryType, received: Instant, payload: Vec<u8>) {
+        let (Some(sender), Some(start)) = (&self.sender, self.start) else {
+            return;
+        };
+
+        let time_micros = received.saturating_duration_since(start).as_micros() as u64;
+
+        if sender
+            .send(RecordEntry {
+                entry_type,
+                time_micros,
+                payload,
+            })
+            .is_err()
+        {
+            // the writer thread has exited due to an error
+            self.stop();
+        }
+    }
+
+    pub fn record_state(&mut self, received: Instant, state: &GameState) {
+        if self.is_recording() {
+            self.record(ReplayEntryType::GameState, received, state.to_bytes());
+        }
+    }
+
+    pub fn record_render(&mut self, received: Instant, message: &RenderMessage) {
+        if self.is_recording() {
+            self.record(ReplayEntryType::Render, received, message.to_bytes());
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+pub enum ReplayPacket {
+    State(GameState),
+    Render(RenderMessage),
+}
+
+pub struct ReplayEntry {
+    /// Seconds since the recording started
+    pub time: f32,
+    pub packet: ReplayPacket,
+}
+
+pub fn read_replay(path: &Path) -> io::Result<Vec<ReplayEntry>> {
+    const ENTRY_HEADER_BYTES: usize = 1 + 8 + 4;
+
+    let bytes = fs::read(path)?;
+
+    if bytes.len() < REPLAY_MAGIC.len() + 1 || bytes[..REPLAY_MAGIC.len()] != REPLAY_MAGIC {
+        return Err(io::Error::new(ErrorKind::InvalidData, "not an RLViser replay file"));
+    }
+
+    let version = bytes[REPLAY_MAGIC.len()];
+    if version != REPLAY_VERSION {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("unsupported replay version {version}, expected {REPLAY_VERSION}"),
+        ));
+    }
+
+    let mut entries = Vec::new();
+    let mut idx = REPLAY_MAGIC.len() + 1;
+
+    while idx + ENTRY_HEADER_BYTES <= bytes.len() {
+        let entry_type = ReplayEntryType::new(bytes[idx])
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, format!("unknown replay entry type {}", bytes[idx])))?;
+        let time_micros = u64::from_le_bytes(bytes[idx + 1..idx + 9].try_into().unwrap());
+        let len = u32::from_le_bytes(bytes[idx + 9..idx + ENTRY_HEADER_BYTES].try_into().unwrap()) as usize;
+        idx += ENTRY_HEADER_BYTES;
+
+        let Some(payload) = bytes.get(idx..idx + len) else {
+            // the recording was most likely interrupted while writing the last entry
+            warn!("Ignoring truncated entry at the end of {}", path.display());
+            break;
+        };
+        idx += len;
+
+        let packet = match entry_type {
+            ReplayEntryType::GameState => GameState::from_bytes(payload).map(ReplayPacket::State),
+            ReplayEntryType::Render => RenderMessage::from_bytes(payload).map(ReplayPacket::Render),
+        };
+
+        let packet = match packet {
+            Ok(packet) => packet,
+            Err(e) => {
+                warn!("Skipping bad entry in {}: {e}", path.display());
+                continue;
+            }
+        };
+
+        entries.push(ReplayEntry {
+            time: (time_micros as f64 / 1_000_000.) as f32,
+            packet,
+        });
+    }
+
+    Ok(entries)
+}
+
+enum PlaybackRequest {
+    Seek(f32),
+    Step { forward: bool },
+}
+
+/// Feeds the packets of a replay file into the normal packet handling at their recorded cadence
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    entries: Vec<ReplayEntry>,
+    updates: Sender<PeerUpdate>,
+    /// Index of the next entry to send
+    cursor: usize,
+    time: f32,
+    request: Option<PlaybackRequest>,
+}
+
+impl ReplayPlayback {
+    pub fn new(entries: Vec<ReplayEntry>, updates: Sender<PeerUpdate>) -> Self {
+        Self {
+            entries,
+            updates,
+            cursor: 0,
+            time: 0.,
+            request: None,
+        }
+    }
+
+    #[inline]
+    pub const fn time(&self) -> f32 {
+        self.time
+    }
+
+    #[inline]
+    pub fn duration(&self) -> f32 {
+        self.entries.last().map(|entry| entry.time).unwrap_or_default()
+    }
+
+    #[inline]
+    pub fn seek(&mut self, time: f32) {
+        self.request = Some(PlaybackRequest::Seek(time));
+    }
+
+    /// Moves to the next or previous recorded game state
+    #[inline]
+    pub fn step(&mut self, forward: bool) {
+        self.request = Some(PlaybackRequest::Step { forward });
+    }
+
+    fn send(&self, index: usize) {
+        let received = Instant::now();
+
+        let update = match &self.entries[index].packet {
+            ReplayPacket::State(state) => UdpUpdate::State(state.clone(), received),
+            ReplayPacket::Render(message) => UdpUpdate::Render(message.clone(), received),
+        };
+
+        drop(self.updates.send(PeerUpdate {
+            peer: Peer::Replay,
+            update,
+        }));
+    }
+
+    fn is_state(&self, index: usize) -> bool {
+        matches!(self.entries[index].packet, ReplayPacket::State(_))
+    }
+
+    /// Send every entry up to and including `time`
+    fn play_until(&mut self, time: f32) {
+        while self.cursor < self.entries.len() && self.entries[self.cursor].time <= time {
+            self.send(self.cursor);
+            self.cursor += 1;
+        }
+
+        self.time = time.clamp(0., self.duration());
+    }
+
+    /// Rebuild the render groups and game state as they were at `time`
+    fn jump_to(&mut self, time: f32, render_groups: &mut RenderGroups) {
+        let time = time.clamp(0., self.duration());
+        render_groups.clear();
+
+        let end = self.entries.partition_point(|entry| entry.time <= time);
+        let last_state = (0..end).rev().find(|&i| self.is_state(i));
+
+        for i in 0..end {
+            if !self.is_state(i) {
+                self.send(i);
+            }
+        }
+
+        if let Some(i) = last_state {
+            self.send(i);
+        }
+
+        self.cursor = end;
+        self.time = time;
+    }
+
+    fn step_to(&mut self, forward: bool, render_groups: &mut RenderGroups) {
+        if forward {
+            let Some(next_state) = (self.cursor..self.entries.len()).find(|&i| self.is_state(i)) else {
+                return;
+            };
+
+            self.play_until(self.entries[next_state].time);
+        } else {
+            // the state before the one currently on screen
+            let Some(previous_state) = (0..self.cursor).rev().filter(|&i| self.is_state(i)).nth(1) else {
+                return;
+            };
+
+            self.jump_to(self.entries[previous_state].time, render_groups);
+        }
+    }
+}
+
+pub fn advance_replay(
+    time: Res<Time>,
+    options: Res<Options>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut render_groups: ResMut<RenderGroups>,
+) {
+    match playback.request.take() {
+        Some(PlaybackRequest::Seek(target)) => playback.jump_to(target, &mut render_groups),
+        Some(PlaybackRequest::Step { forward }) => playback.step_to(forward, &mut render_groups),
+        None if !options.paused => {
+            let target = playback.time + time.delta_seconds() * options.game_speed;
+            playback.play_until(target);
+        }
+        None => {}
+    }
+}
@@ -1,11 +1,19 @@
 use super::options::{
-    BallCam, CalcBallRot, GameOptions, GameSpeed, MenuFocused, Options, PacketSmoothing, ShowTime, UiOverlayScale,
+    BallCam, CalcBallRot, GameOptions, GameSpeed, MenuFocused, Options, PacketSmoothing, ShowTime, StartingCamera,
+    UiOverlayScale,
 };
 use crate::{
     camera::{DaylightOffset, PrimaryCamera, Sun},
-    renderer::{DoRendering, RenderGroups},
+    heatmap::{Heatmap, HeatmapSource},
+    history::StateHistory,
+    peers::Peers,
+    renderer::{DoRendering, RenderGroups, Solo},
+    replay::{Recorder, ReplayPlayback},
+    rocketsim::Team,
     spectator::SpectatorSettings,
-    udp::{Connection, PausedUpdate, SendableUdp, SpeedUpdate},
+    telemetry::Telemetry,
+    touches::TouchHistory,
+    udp::{Connection, PausedUpdate, SendableUdp, SpeedUpdate, PROTOCOL_VERSION},
 };
 use bevy::{
     pbr::DirectionalLightShadowMap,
@@ -13,7 +21,7 @@ use bevy::{
     time::Stopwatch,
     window::{CursorGrabMode, PrimaryWindow},
 };
-use std::time::Duration;
+use std::{collections::BTreeMap, time::Duration};
 
 use super::state_setting::StateSettingInterface;
 use bevy_egui::{
@@ -55,6 +63,7 @@ impl Plugin for DebugOverlayPlugin {
                         update_render_info,
                         update_packet_smoothing,
                         update_calc_ball_rot,
+                        update_rewind_length,
                         (
                             update_speed
                                 .run_if(|options: Res<Options>, last: Res<GameSpeed>| options.game_speed != last.speed),
@@ -137,6 +146,14 @@ fn ui_system(
     mut options: ResMut<Options>,
     mut contexts: EguiContexts,
     render_info: Res<RenderInfo>,
+    mut recorder: ResMut<Recorder>,
+    playback: Option<ResMut<ReplayPlayback>>,
+    mut history: ResMut<StateHistory>,
+    mut peers: ResMut<Peers>,
+    mut renders: ResMut<RenderGroups>,
+    mut touches: ResMut<TouchHistory>,
+    mut telemetry: ResMut<Telemetry>,
+    mut heatmap: ResMut<Heatmap>,
     time: Res<Time>,
 ) {
     #[cfg(not(feature = "ssao"))]
@@ -166,6 +183,104 @@ fn ui_system(
         .show(ctx, |ui| {
             ui.label(format!("FPS: {fps:.0}"));
 
+            if let Some(mut playback) = playback {
+                CollapsingHeader::new("Replay").default_open(true).show(ui, |ui| {
+                    let duration = playback.duration();
+                    let mut time = playback.time();
+
+                    if ui
+                        .add(egui::Slider::new(&mut time, 0.0..=duration).show_value(false))
+                        .changed()
+                    {
+                        playback.seek(time);
+                    }
+
+                    ui.label(format!("{time:.2}s / {duration:.2}s"));
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Restart").clicked() {
+                            playback.seek(0.);
+                        }
+
+                        if ui.button("< Step").on_hover_text("Go back one tick").clicked() {
+                            options.paused = true;
+                            playback.step(false);
+                        }
+
+                        if ui.button(if options.paused { "Play" } else { "Pause" }).clicked() {
+                            options.paused = !options.paused;
+                        }
+
+                        if ui.button("Step >").on_hover_text("Go forward one tick").clicked() {
+                            options.paused = true;
+                            playback.step(true);
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Playback speed");
+                        ui.add(
+                            egui::DragValue::new(&mut options.game_speed)
+                                .range(0.1..=10.0)
+                                .speed(0.02)
+                                .fixed_decimals(1),
+                        );
+                    });
+                });
+            }
+
+            ui.collapsing("Connections", |ui| {
+                let mut selected = None;
+
+                for (peer, info) in peers.iter() {
+                    let text = format!(
+                        "{peer}: {:?}, {} cars, tick {}, last seen {:.1}s ago",
+                        info.game_mode,
+                        info.num_cars,
+                        info.tick_count,
+                        info.last_seen.elapsed().as_secs_f32()
+                    );
+
+                    if ui.selectable_label(peers.is_active(peer), text).clicked() {
+                        selected = Some(peer);
+                    }
+
+                    match info.handshake {
+                        Some(handshake) if !handshake.is_compatible() => {
+                            ui.colored_label(
+                                egui::Color32::RED,
+                                format!(
+                                    "Incompatible protocol version {}, expected {PROTOCOL_VERSION}",
+                                    handshake.version
+                                ),
+                            );
+                        }
+                        Some(handshake) => {
+                            ui.label(format!("Protocol version {}", handshake.version));
+                        }
+                        None => {
+                            ui.label("Legacy client without a handshake");
+                        }
+                    }
+
+                    if info.num_bad_packets != 0 {
+                        ui.colored_label(egui::Color32::YELLOW, format!("Dropped {} bad packets", info.num_bad_packets));
+
+                        if let Some(e) = &info.last_error {
+                            ui.label(format!("Last error: {e}"));
+                        }
+                    }
+                }
+
+                if let Some(peer) = selected {
+                    peers.select(peer);
+                }
+
+                if peers.is_empty() {
+                    ui.label("Waiting for a simulation to connect");
+                }
+            });
+
             ui.collapsing("Graphics", |ui| {
                 ui.horizontal(|ui| {
                     ui.checkbox(&mut options.vsync, "vsync");
@@ -224,6 +339,107 @@ fn ui_system(
                 ui.add(egui::Slider::new(&mut options.day_speed, 0.0..=10.0).text("Day speed"));
             });
 
+            ui.collapsing("Trails", |ui| {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut options.ball_trail, "Ball");
+                    ui.color_edit_button_rgb(&mut options.ball_trail_color);
+                });
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut options.car_trails, "Cars");
+                    ui.color_edit_button_rgb(&mut options.blue_trail_color);
+                    ui.color_edit_button_rgb(&mut options.orange_trail_color);
+                });
+
+                ui.add(egui::Slider::new(&mut options.trail_seconds, 0.1..=10.0).text("Length (s)"))
+                    .on_hover_text("Trails can't be longer than the rewind buffer");
+                ui.checkbox(&mut options.trail_fade, "Fade out");
+            });
+
+            ui.collapsing("Ball prediction", |ui| {
+                ui.checkbox(&mut options.ball_prediction, "Show predicted path")
+                    .on_hover_text("A rough local prediction with flat walls, to check bots' predictions against");
+                ui.add(egui::Slider::new(&mut options.prediction_seconds, 0.5..=10.0).text("Length (s)"));
+            });
+
+            ui.collapsing("Effects", |ui| {
+                ui.checkbox(&mut options.demo_ghosts, "Demolished car countdowns")
+                    .on_hover_text("Outline demolished cars where they were destroyed, until they respawn");
+                ui.checkbox(&mut options.particles, "Boost, supersonic and jump particles");
+            });
+
+            ui.collapsing("Inputs", |ui| {
+                ui.checkbox(&mut options.input_overlay, "Show the focused car's inputs");
+                ui.checkbox(&mut options.input_grid, "Show every car's inputs");
+            });
+
+            ui.collapsing("Telemetry", |ui| {
+                ui.checkbox(&mut telemetry.open, "Show graphs")
+                    .on_hover_text("Chart speed, boost, height and more over time");
+            });
+
+            ui.collapsing("Heatmap", |ui| {
+                ui.checkbox(&mut options.heatmap, "Show on the field");
+
+                egui::ComboBox::from_label("Source")
+                    .selected_text(heatmap.source.name())
+                    .show_ui(ui, |ui| {
+                        let sources = [
+                            HeatmapSource::Ball,
+                            HeatmapSource::Team(Team::Blue),
+                            HeatmapSource::Team(Team::Orange),
+                        ]
+                        .into_iter()
+                        .chain(heatmap.car_ids().into_iter().map(HeatmapSource::Car));
+
+                        for source in sources {
+                            ui.selectable_value(&mut heatmap.source, source, source.name());
+                        }
+                    });
+
+                ui.add(egui::Slider::new(&mut options.heatmap_opacity, 0.0..=1.0).text("Opacity"));
+
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} samples", heatmap.num_samples()));
+
+                    if ui.button("Reset").clicked() {
+                        heatmap.reset();
+                    }
+                });
+            });
+
+            ui.collapsing("Touches", |ui| {
+                ui.checkbox(&mut options.touch_markers, "Highlight touches")
+                    .on_hover_text("Flash the ball and mark where it was hit, with an arrow for the extra impulse");
+
+                if touches.is_empty() {
+                    ui.label("No touches yet");
+                    return;
+                }
+
+                egui::ScrollArea::vertical().max_height(200.).show(ui, |ui| {
+                    egui::Grid::new("touches").striped(true).show(ui, |ui| {
+                        ui.label("Tick");
+                        ui.label("Car");
+                        ui.label("Ball speed before");
+                        ui.label("After");
+                        ui.end_row();
+
+                        for touch in touches.iter() {
+                            ui.label(touch.tick_count.to_string());
+                            ui.label(format!("{} ({:?})", touch.car_id, touch.team));
+                            ui.label(format!("{:.0}", touch.ball_speed_before));
+                            ui.label(format!("{:.0}", touch.ball_speed_after));
+                            ui.end_row();
+                        }
+                    });
+                });
+
+                if ui.button("Clear").clicked() {
+                    touches.clear();
+                }
+            });
+
             ui.collapsing("Rendering manager", |ui| {
                 ui.checkbox(&mut options.allow_rendering, "Allow rendering");
 
@@ -231,16 +447,132 @@ fn ui_system(
 
                 ui.label(format!("Groups: {}", render_info.groups));
                 ui.label(format!("Items: {}", render_info.items));
+
+                ui.add_space(10.);
+
+                render_groups_ui(ui, &mut renders);
+            });
+
+            ui.collapsing("Rewind", |ui| {
+                if let Some(mut index) = history.view_index() {
+                    let last_index = history.len().saturating_sub(1);
+
+                    if ui
+                        .add(egui::Slider::new(&mut index, 0..=last_index).show_value(false))
+                        .changed()
+                    {
+                        history.set_view_index(index);
+                    }
+
+                    ui.label(format!("{:.2}s behind live", history.time_behind_live().as_secs_f32()));
+
+                    ui.horizontal(|ui| {
+                        if ui.button("< Step").on_hover_text("Go back one packet").clicked() {
+                            history.set_view_index(index.saturating_sub(1));
+                        }
+
+                        if ui.button("Step >").on_hover_text("Go forward one packet").clicked() {
+                            history.set_view_index(index + 1);
+                        }
+
+                        if ui.button("Go live").clicked() {
+                            history.go_live();
+                        }
+                    });
+                } else if ui
+                    .add_enabled(!history.is_empty(), egui::Button::new("Pause view"))
+                    .on_hover_text("Freeze the view locally while the simulation keeps running")
+                    .clicked()
+                {
+                    history.pause_view();
+                }
+
+                ui.add(egui::Slider::new(&mut options.rewind_seconds, 1.0..=300.0).text("Buffer length (s)"));
+            });
+
+            ui.collapsing("Recording", |ui| {
+                let mut recording = recorder.is_recording();
+                if ui.checkbox(&mut recording, "Record packets").changed() {
+                    if recording {
+                        if let Err(e) = recorder.start(Recorder::default_path()) {
+                            error!("Failed to start recording due to: {e}");
+                        }
+                    } else {
+                        recorder.stop();
+                    }
+                }
+
+                if let Some(path) = recorder.path() {
+                    ui.label(format!("File: {}", path.display()));
+                }
             });
         });
 }
 
+fn render_groups_ui(ui: &mut egui::Ui, renders: &mut ResMut<RenderGroups>) {
+    let mut categories = BTreeMap::<String, Vec<i32>>::new();
+    for group_id in renders.groups.keys() {
+        categories
+            .entry(renders.category(*group_id).to_string())
+            .or_default()
+            .push(*group_id);
+    }
+
+    if let Some(solo) = renders.solo().cloned() {
+        if ui.button("Stop soloing").clicked() {
+            renders.toggle_solo(solo);
+        }
+    }
+
+    for (category, mut group_ids) in categories {
+        group_ids.sort_unstable();
+
+        let num_items = group_ids.iter().map(|group_id| renders.groups[group_id].len()).sum::<usize>();
+        let label = if category.is_empty() { "Uncategorized" } else { &category };
+
+        ui.horizontal(|ui| {
+            let mut visible = !renders.is_category_hidden(&category);
+            if ui.checkbox(&mut visible, format!("{label} ({num_items} items)")).changed() {
+                renders.set_category_hidden(&category, !visible);
+            }
+
+            let solo = Solo::Category(category.clone());
+            if ui.selectable_label(renders.solo() == Some(&solo), "Solo").clicked() {
+                renders.toggle_solo(solo);
+            }
+        });
+
+        ui.indent(&category, |ui| {
+            for group_id in group_ids {
+                let name = renders
+                    .info(group_id)
+                    .map_or_else(|| format!("Group {group_id}"), |info| format!("{} ({group_id})", info.name));
+                let num_items = renders.groups[&group_id].len();
+
+                ui.horizontal(|ui| {
+                    let mut visible = !renders.is_group_hidden(group_id);
+                    if ui.checkbox(&mut visible, format!("{name}: {num_items} items")).changed() {
+                        renders.set_group_hidden(group_id, !visible);
+                    }
+
+                    let solo = Solo::Group(group_id);
+                    if ui.selectable_label(renders.solo() == Some(&solo), "Solo").clicked() {
+                        renders.toggle_solo(solo);
+                    }
+                });
+            }
+        });
+    }
+}
+
 fn update_allow_rendering(options: Res<Options>, mut do_rendering: ResMut<DoRendering>, mut renders: ResMut<RenderGroups>) {
     if !options.allow_rendering {
-        renders.groups.clear();
+        RenderGroups::update(&mut renders, RenderGroups::clear);
     }
 
-    do_rendering.0 = options.allow_rendering;
+    if do_rendering.0 != options.allow_rendering {
+        do_rendering.0 = options.allow_rendering;
+    }
 }
 
 fn update_sensitivity(options: Res<Options>, mut settings: ResMut<SpectatorSettings>) {
@@ -323,6 +655,10 @@ fn toggle_vsync(options: Res<Options>, mut framepace: ResMut<FramepaceSettings>)
     };
 }
 
+fn update_rewind_length(options: Res<Options>, mut history: ResMut<StateHistory>) {
+    history.set_max_age(Duration::from_secs_f32(options.rewind_seconds));
+}
+
 fn update_calc_ball_rot(options: Res<Options>, mut calc_ball_rot: ResMut<CalcBallRot>) {
     calc_ball_rot.0 = options.calc_ball_rot;
 }
@@ -384,14 +720,21 @@ fn write_settings_to_file(
     }
 }
 
-fn update_camera_state(mut primary_camera: Query<&mut PrimaryCamera>, options: Res<Options>) {
-    if PrimaryCamera::Director(0) == options.camera_state {
+fn update_camera_state(
+    mut primary_camera: Query<&mut PrimaryCamera>,
+    options: Res<Options>,
+    starting_camera: Res<StartingCamera>,
+) {
+    // the camera from the command line isn't saved to the settings, so it's used until another one is picked
+    let camera_state = starting_camera.0.unwrap_or(options.camera_state);
+
+    if PrimaryCamera::Director(0) == camera_state {
         if let PrimaryCamera::Director(_) = primary_camera.single() {
             return;
         }
     }
 
-    *primary_camera.single_mut() = options.camera_state;
+    *primary_camera.single_mut() = camera_state;
 }
 
 fn listen(
@@ -401,6 +744,7 @@ fn listen(
     mut menu_focused: ResMut<MenuFocused>,
     mut last_focus: Local<bool>,
     mut options: ResMut<Options>,
+    mut starting_camera: ResMut<StartingCamera>,
 ) {
     if key.just_pressed(KeyCode::Escape) {
         menu_focused.0 = !menu_focused.0;
@@ -426,25 +770,33 @@ fn listen(
         return;
     }
 
-    if key.just_pressed(KeyCode::Digit1) || key.just_pressed(KeyCode::Numpad1) {
-        options.camera_state = PrimaryCamera::TrackCar(1);
+    let camera = if key.just_pressed(KeyCode::Digit1) || key.just_pressed(KeyCode::Numpad1) {
+        Some(PrimaryCamera::TrackCar(1))
     } else if key.just_pressed(KeyCode::Digit2) || key.just_pressed(KeyCode::Numpad2) {
-        options.camera_state = PrimaryCamera::TrackCar(2);
+        Some(PrimaryCamera::TrackCar(2))
     } else if key.just_pressed(KeyCode::Digit3) || key.just_pressed(KeyCode::Numpad3) {
-        options.camera_state = PrimaryCamera::TrackCar(3);
+        Some(PrimaryCamera::TrackCar(3))
     } else if key.just_pressed(KeyCode::Digit4) || key.just_pressed(KeyCode::Numpad4) {
-        options.camera_state = PrimaryCamera::TrackCar(4);
+        Some(PrimaryCamera::TrackCar(4))
     } else if key.just_pressed(KeyCode::Digit5) || key.just_pressed(KeyCode::Numpad5) {
-        options.camera_state = PrimaryCamera::TrackCar(5);
+        Some(PrimaryCamera::TrackCar(5))
     } else if key.just_pressed(KeyCode::Digit6) || key.just_pressed(KeyCode::Numpad2) {
-        options.camera_state = PrimaryCamera::TrackCar(6);
+        Some(PrimaryCamera::TrackCar(6))
     } else if key.just_pressed(KeyCode::Digit7) || key.just_pressed(KeyCode::Numpad7) {
-        options.camera_state = PrimaryCamera::TrackCar(7);
+        Some(PrimaryCamera::TrackCar(7))
     } else if key.just_pressed(KeyCode::Digit8) || key.just_pressed(KeyCode::Numpad8) {
-        options.camera_state = PrimaryCamera::TrackCar(8);
+        Some(PrimaryCamera::TrackCar(8))
     } else if key.just_pressed(KeyCode::Digit9) || key.just_pressed(KeyCode::Numpad9) {
-        options.camera_state = PrimaryCamera::Director(0);
+        Some(PrimaryCamera::Director(0))
     } else if key.just_pressed(KeyCode::Digit0) || key.just_pressed(KeyCode::Numpad0) {
-        options.camera_state = PrimaryCamera::Spectator;
+        Some(PrimaryCamera::Spectator)
+    } else {
+        None
+    };
+
+    if let Some(camera) = camera {
+        // picking a camera replaces the one from the command line
+        starting_camera.0 = None;
+        options.camera_state = camera;
     }
 }
@@ -1,10 +1,14 @@
 use crate::camera::PrimaryCamera;
 use bevy::prelude::*;
+use once_cell::sync::OnceCell;
 use std::{
     fs,
     io::{self, Write},
+    path::{Path, PathBuf},
 };
 
+static FILE_PATH: OnceCell<PathBuf> = OnceCell::new();
+
 pub struct GameOptions;
 
 impl Plugin for GameOptions {
@@ -21,6 +25,10 @@ impl Plugin for GameOptions {
     }
 }
 
+/// Camera to start with instead of the one saved in the settings, until another one is picked
+#[derive(Resource, Default)]
+pub struct StartingCamera(pub Option<PrimaryCamera>);
+
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Clone, Resource)]
 pub struct Options {
@@ -43,6 +51,23 @@ pub struct Options {
     pub allow_rendering: bool,
     pub packet_smoothing: usize,
     pub calc_ball_rot: bool,
+    pub rewind_seconds: f32,
+    pub ball_trail: bool,
+    pub car_trails: bool,
+    pub trail_seconds: f32,
+    pub trail_fade: bool,
+    pub ball_trail_color: [f32; 3],
+    pub blue_trail_color: [f32; 3],
+    pub orange_trail_color: [f32; 3],
+    pub ball_prediction: bool,
+    pub prediction_seconds: f32,
+    pub demo_ghosts: bool,
+    pub particles: bool,
+    pub touch_markers: bool,
+    pub input_overlay: bool,
+    pub input_grid: bool,
+    pub heatmap: bool,
+    pub heatmap_opacity: f32,
 }
 
 impl Default for Options {
@@ -69,6 +94,23 @@ impl Default for Options {
             allow_rendering: true,
             packet_smoothing: 1,
             calc_ball_rot: true,
+            rewind_seconds: 30.,
+            ball_trail: false,
+            car_trails: false,
+            trail_seconds: 2.,
+            trail_fade: true,
+            ball_trail_color: [1., 1., 1.],
+            blue_trail_color: [0.2, 0.5, 1.],
+            orange_trail_color: [1., 0.5, 0.1],
+            ball_prediction: false,
+            prediction_seconds: 3.,
+            demo_ghosts: true,
+            particles: true,
+            touch_markers: true,
+            input_overlay: true,
+            input_grid: false,
+            heatmap: false,
+            heatmap_opacity: 0.6,
         }
     }
 }
@@ -76,6 +118,17 @@ impl Default for Options {
 impl Options {
     const FILE_NAME: &'static str = "settings.txt";
 
+    /// Use a different settings file, which has to happen before the options are first read
+    pub fn set_file_path(path: PathBuf) {
+        if FILE_PATH.set(path).is_err() {
+            println!("The settings file path was already set");
+        }
+    }
+
+    fn file_path() -> &'static Path {
+        FILE_PATH.get_or_init(|| PathBuf::from(Self::FILE_NAME))
+    }
+
     #[inline]
     fn default_read_file() -> Self {
         Self::read_from_file().unwrap_or_else(|_| Self::create_file_from_defualt())
@@ -84,7 +137,7 @@ impl Options {
     fn read_from_file() -> io::Result<Self> {
         let mut options = Self::default();
 
-        let file = fs::read_to_string(Self::FILE_NAME)?;
+        let file = fs::read_to_string(Self::file_path())?;
 
         for line in file.lines() {
             let mut parts = line.split('=');
@@ -116,6 +169,23 @@ impl Options {
                 "allow_rendering" => options.allow_rendering = value.parse().unwrap(),
                 "packet_smoothing" => options.packet_smoothing = serde_json::from_str(value).unwrap(),
                 "calc_ball_rot" => options.calc_ball_rot = value.parse().unwrap(),
+                "rewind_seconds" => options.rewind_seconds = value.parse().unwrap(),
+                "ball_trail" => options.ball_trail = value.parse().unwrap(),
+                "car_trails" => options.car_trails = value.parse().unwrap(),
+                "trail_seconds" => options.trail_seconds = value.parse().unwrap(),
+                "trail_fade" => options.trail_fade = value.parse().unwrap(),
+                "ball_trail_color" => options.ball_trail_color = serde_json::from_str(value).unwrap(),
+                "blue_trail_color" => options.blue_trail_color = serde_json::from_str(value).unwrap(),
+                "orange_trail_color" => options.orange_trail_color = serde_json::from_str(value).unwrap(),
+                "ball_prediction" => options.ball_prediction = value.parse().unwrap(),
+                "prediction_seconds" => options.prediction_seconds = value.parse().unwrap(),
+                "demo_ghosts" => options.demo_ghosts = value.parse().unwrap(),
+                "particles" => options.particles = value.parse().unwrap(),
+                "touch_markers" => options.touch_markers = value.parse().unwrap(),
+                "input_overlay" => options.input_overlay = value.parse().unwrap(),
+                "input_grid" => options.input_grid = value.parse().unwrap(),
+                "heatmap" => options.heatmap = value.parse().unwrap(),
+                "heatmap_opacity" => options.heatmap_opacity = value.parse().unwrap(),
                 _ => println!("Unknown key {key} with value {value}"),
             }
         }
@@ -127,14 +197,14 @@ impl Options {
         let options = Self::default();
 
         if let Err(e) = options.write_options_to_file() {
-            println!("Failed to create {} due to: {e}", Self::FILE_NAME);
+            println!("Failed to create {} due to: {e}", Self::file_path().display());
         }
 
         options
     }
 
     pub fn write_options_to_file(&self) -> io::Result<()> {
-        let mut file = fs::File::create(Self::FILE_NAME)?;
+        let mut file = fs::File::create(Self::file_path())?;
 
         file.write_fmt(format_args!("vsync={}\n", self.vsync))?;
         file.write_fmt(format_args!("uncap_fps={}\n", self.uncap_fps))?;
@@ -154,6 +224,32 @@ impl Options {
         file.write_fmt(format_args!("allow_rendering={}\n", self.allow_rendering))?;
         file.write_fmt(format_args!("packet_smoothing={}\n", self.packet_smoothing))?;
         file.write_fmt(format_args!("calc_ball_rot={}\n", self.calc_ball_rot))?;
+        file.write_fmt(format_args!("rewind_seconds={}\n", self.rewind_seconds))?;
+        file.write_fmt(format_args!("ball_trail={}\n", self.ball_trail))?;
+        file.write_fmt(format_args!("car_trails={}\n", self.car_trails))?;
+        file.write_fmt(format_args!("trail_seconds={}\n", self.trail_seconds))?;
+        file.write_fmt(format_args!("trail_fade={}\n", self.trail_fade))?;
+        file.write_fmt(format_args!(
+            "ball_trail_color={}\n",
+            serde_json::to_string(&self.ball_trail_color)?
+        ))?;
+        file.write_fmt(format_args!(
+            "blue_trail_color={}\n",
+            serde_json::to_string(&self.blue_trail_color)?
+        ))?;
+        file.write_fmt(format_args!(
+            "orange_trail_color={}\n",
+            serde_json::to_string(&self.orange_trail_color)?
+        ))?;
+        file.write_fmt(format_args!("ball_prediction={}\n", self.ball_prediction))?;
+        file.write_fmt(format_args!("prediction_seconds={}\n", self.prediction_seconds))?;
+        file.write_fmt(format_args!("demo_ghosts={}\n", self.demo_ghosts))?;
+        file.write_fmt(format_args!("particles={}\n", self.particles))?;
+        file.write_fmt(format_args!("touch_markers={}\n", self.touch_markers))?;
+        file.write_fmt(format_args!("input_overlay={}\n", self.input_overlay))?;
+        file.write_fmt(format_args!("input_grid={}\n", self.input_grid))?;
+        file.write_fmt(format_args!("heatmap={}\n", self.heatmap))?;
+        file.write_fmt(format_args!("heatmap_opacity={}\n", self.heatmap_opacity))?;
 
         Ok(())
     }
@@ -179,6 +275,23 @@ impl Options {
             || self.allow_rendering != other.allow_rendering
             || self.packet_smoothing != other.packet_smoothing
             || self.calc_ball_rot != other.calc_ball_rot
+            || self.rewind_seconds != other.rewind_seconds
+            || self.ball_trail != other.ball_trail
+            || self.car_trails != other.car_trails
+            || self.trail_seconds != other.trail_seconds
+            || self.trail_fade != other.trail_fade
+            || self.ball_trail_color != other.ball_trail_color
+            || self.blue_trail_color != other.blue_trail_color
+            || self.orange_trail_color != other.orange_trail_color
+            || self.ball_prediction != other.ball_prediction
+            || self.prediction_seconds != other.prediction_seconds
+            || self.demo_ghosts != other.demo_ghosts
+            || self.particles != other.particles
+            || self.touch_markers != other.touch_markers
+            || self.input_overlay != other.input_overlay
+            || self.input_grid != other.input_grid
+            || self.heatmap != other.heatmap
+            || self.heatmap_opacity != other.heatmap_opacity
     }
 }
 
@@ -0,0 +1,270 @@
+use crate::{
+    bytes::{DecodeError, FromBytesExact},
+    peers::Peer,
+    renderer::RenderMessage,
+    rocketsim::GameState,
+    udp::{Handshake, PeerUpdate, SendableUdp, UdpPacketTypes, UdpUpdate},
+};
+use ahash::HashMap;
+use bevy::prelude::*;
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex},
+    thread,
+    time::Instant,
+};
+
+#[cfg(unix)]
+use std::{
+    fs,
+    os::unix::{fs::FileTypeExt, net::UnixListener},
+    path::PathBuf,
+};
+
+/// Packets bigger than this are assumed to be garbage instead of allocating for them
+const MAX_PACKET_BYTES: usize = 64 * 1024 * 1024;
+
+/// How packets are received from the simulation
+#[derive(Clone, Debug, Default)]
+pub enum Transport {
+    #[default]
+    Udp,
+    Stream(StreamTransport),
+}
+
+/// Transports that accept connections instead of receiving datagrams
+#[derive(Clone, Debug)]
+pub enum StreamTransport {
+    /// Listen for TCP connections on the bind address and secondary port
+    Tcp,
+    /// Listen for connections on a Unix domain socket at this path
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// How many packets can be waiting to be written to a connection before new ones are dropped
+const MAX_QUEUED_PACKETS: usize = 256;
+
+/// Packets waiting to be written to each connection, on its own thread so one slow peer can't hold up the others
+type Writers = Arc<Mutex<HashMap<Peer, Sender<Arc<[u8]>>>>>;
+
+/// Accept stream connections in the background
+///
+/// Packets are sent the same way as over UDP, the packet type byte followed by its payload,
+/// except that there's no limit to the size of the payload and it always arrives in order.
+pub fn listen(
+    transport: &StreamTransport,
+    bind_addr: SocketAddr,
+    updates: Sender<PeerUpdate>,
+    outgoing: Receiver<SendableUdp>,
+) -> io::Result<()> {
+    let writers = Writers::default();
+
+    match transport {
+        StreamTransport::Tcp => {
+            let listener = TcpListener::bind(bind_addr)?;
+            info!("Listening for TCP connections on {bind_addr}");
+
+            let writers = writers.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+
+                    let Ok(addr) = stream.peer_addr() else {
+                        continue;
+                    };
+
+                    // packets are small and frequent, so don't wait to batch them
+                    drop(stream.set_nodelay(true));
+
+                    if let Ok(writer) = stream.try_clone() {
+                        start_stream_reader(stream, writer, Peer::Addr(addr), &writers, updates.clone());
+                    }
+                }
+            });
+        }
+        #[cfg(unix)]
+        StreamTransport::Unix(path) => {
+            // a socket file left behind by a previous run would make binding fail, but anything else is left alone
+            match fs::symlink_metadata(path) {
+                Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        ErrorKind::AlreadyExists,
+                        format!("{} already exists and isn't a socket", path.display()),
+                    ))
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+
+            let listener = UnixListener::bind(path)?;
+            info!("Listening for connections on {}", path.display());
+
+            let writers = writers.clone();
+            thread::spawn(move || {
+                for (id, stream) in (0..).zip(listener.incoming()) {
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+
+                    if let Ok(writer) = stream.try_clone() {
+                        start_stream_reader(stream, writer, Peer::Local(id), &writers, updates.clone());
+                    }
+                }
+            });
+        }
+    }
+
+    start_stream_writer(writers, outgoing);
+
+    Ok(())
+}
+
+fn start_stream_reader(
+    stream: impl Read + Send + 'static,
+    writer: impl Write + Send + 'static,
+    peer: Peer,
+    writers: &Writers,
+    updates: Sender<PeerUpdate>,
+) {
+    info!("{peer} connected");
+
+    let (queue, queued) = crossbeam_channel::bounded::<Arc<[u8]>>(MAX_QUEUED_PACKETS);
+    writers.lock().unwrap().insert(peer, queue);
+
+    // stops once the connection is removed from the writers, or can't be written to anymore
+    thread::spawn(move || {
+        let mut writer = writer;
+        for packet in queued {
+            if writer.write_all(&packet).is_err() {
+                break;
+            }
+        }
+    });
+
+    let writers = writers.clone();
+    thread::spawn(move || {
+        if let Err(e) = read_packets(stream, peer, &updates) {
+            if e.kind() != ErrorKind::UnexpectedEof {
+                error!("Closing the connection to {peer} due to: {e}");
+            }
+        }
+
+        writers.lock().unwrap().remove(&peer);
+        drop(updates.send(PeerUpdate {
+            peer,
+            update: UdpUpdate::Disconnected,
+        }));
+    });
+}
+
+fn start_stream_writer(writers: Writers, outgoing: Receiver<SendableUdp>) {
+    thread::spawn(move || {
+        // until a simulation has been picked, send to all of them
+        let mut target = None;
+
+        for sendable in outgoing {
+            let to = match sendable {
+                SendableUdp::Target(peer) => {
+                    target = Some(peer);
+                    continue;
+                }
+                SendableUdp::Handshake(peer, _) => Some(peer),
+                _ => target,
+            };
+
+            let Some((packet_type, bytes)) = sendable.to_packet() else {
+                continue;
+            };
+
+            let mut packet = Vec::with_capacity(1 + bytes.len());
+            packet.push(packet_type as u8);
+            packet.extend_from_slice(&bytes);
+            let packet = Arc::<[u8]>::from(packet);
+
+            writers.lock().unwrap().retain(|peer, queue| {
+                if to.is_some_and(|to| to != *peer) {
+                    return true;
+                }
+
+                match queue.try_send(packet.clone()) {
+                    // a peer that isn't keeping up misses this packet instead of holding up the others
+                    Ok(()) | Err(TrySendError::Full(_)) => true,
+                    // the connection can't be written to anymore
+                    Err(TrySendError::Disconnected(_)) => false,
+                }
+            });
+        }
+    });
+}
+
+/// Replace the contents of `buf` with `header` followed by the rest of a `num_bytes` long packet
+fn read_packet(stream: &mut impl Read, buf: &mut Vec<u8>, header: &[u8], num_bytes: usize) -> io::Result<()> {
+    if num_bytes < header.len() || num_bytes > MAX_PACKET_BYTES {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("packet header claims an invalid size of {num_bytes} bytes"),
+        ));
+    }
+
+    buf.clear();
+    buf.extend_from_slice(header);
+    buf.resize(num_bytes, 0);
+    stream.read_exact(&mut buf[header.len()..])
+}
+
+/// Returns when the connection closes, or when the stream can no longer be trusted
+fn read_packets(mut stream: impl Read, peer: Peer, updates: &Sender<PeerUpdate>) -> io::Result<()> {
+    let mut packet_type_buffer = [0];
+    let mut state_header = [0; GameState::MIN_NUM_BYTES];
+    let mut render_header = [0; RenderMessage::MIN_NUM_BYTES];
+    let mut buf = Vec::new();
+
+    loop {
+        stream.read_exact(&mut packet_type_buffer)?;
+
+        let Some(packet_type) = UdpPacketTypes::new(packet_type_buffer[0]) else {
+            // without knowing the packet's size, there's no way to find where the next one starts
+            drop(updates.send(PeerUpdate {
+                peer,
+                update: UdpUpdate::BadPacket(DecodeError::BadTag {
+                    name: "packet type",
+                    tag: packet_type_buffer[0],
+                }),
+            }));
+
+            return Err(io::Error::new(ErrorKind::InvalidData, "unknown packet type"));
+        };
+
+        match packet_type {
+            UdpPacketTypes::Quit | UdpPacketTypes::Connection => buf.clear(),
+            UdpPacketTypes::GameState => {
+                stream.read_exact(&mut state_header)?;
+                read_packet(&mut stream, &mut buf, &state_header, GameState::get_num_bytes(&state_header))?;
+            }
+            UdpPacketTypes::Render => {
+                stream.read_exact(&mut render_header)?;
+                read_packet(
+                    &mut stream,
+                    &mut buf,
+                    &render_header,
+                    RenderMessage::get_num_bytes(&render_header),
+                )?;
+            }
+            UdpPacketTypes::Speed => read_packet(&mut stream, &mut buf, &[], f32::NUM_BYTES)?,
+            UdpPacketTypes::Paused => read_packet(&mut stream, &mut buf, &[], bool::NUM_BYTES)?,
+            UdpPacketTypes::Handshake => read_packet(&mut stream, &mut buf, &[], Handshake::NUM_BYTES)?,
+        }
+
+        let update = UdpUpdate::decode(packet_type, &buf, Instant::now());
+
+        if updates.send(PeerUpdate { peer, update }).is_err() {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use crate::{rocketsim::GameState, udp::NewGameState};
+use bevy::{prelude::*, utils::HashSet};
+use bevy_egui::{egui, EguiContexts};
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+use std::collections::VecDeque;
+
+const MAX_WINDOW_SECONDS: f32 = 60.;
+const PLOT_HEIGHT: f32 = 120.;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Variable {
+    Speed,
+    Boost,
+    Height,
+    BallDistance,
+    AirTime,
+    BallSpeed,
+}
+
+impl Variable {
+    const ALL: [Self; 6] = [
+        Self::Speed,
+        Self::Boost,
+        Self::Height,
+        Self::BallDistance,
+        Self::AirTime,
+        Self::BallSpeed,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Speed => "Speed",
+            Self::Boost => "Boost",
+            Self::Height => "Height",
+            Self::BallDistance => "Distance to ball",
+            Self::AirTime => "Air time",
+            Self::BallSpeed => "Ball speed",
+        }
+    }
+
+    /// The ball's variables are plotted once instead of once per car
+    const fn is_per_car(self) -> bool {
+        !matches!(self, Self::BallSpeed)
+    }
+}
+
+struct CarSample {
+    id: u32,
+    speed: f32,
+    boost: f32,
+    height: f32,
+    ball_distance: f32,
+    air_time: f32,
+}
+
+struct Sample {
+    /// Seconds since the start of the simulation
+    time: f64,
+    ball_speed: f32,
+    cars: Vec<CarSample>,
+}
+
+impl Sample {
+    fn new(state: &GameState) -> Self {
+        Self {
+            time: state.tick_count as f64 / f64::from(state.tick_rate),
+            ball_speed: state.ball.vel.length(),
+            cars: state
+                .cars
+                .iter()
+                .map(|car| CarSample {
+                    id: car.id,
+                    speed: car.state.vel.length(),
+                    boost: car.state.boost,
+                    height: car.state.pos.z,
+                    ball_distance: car.state.pos.distance(state.ball.pos),
+                    air_time: car.state.air_time,
+                })
+                .collect(),
+        }
+    }
+
+    fn value(&self, variable: Variable, car_id: u32) -> Option<f32> {
+        let car = || self.cars.iter().find(|car| car.id == car_id);
+
+        Some(match variable {
+            Variable::Speed => car()?.speed,
+            Variable::Boost => car()?.boost,
+            Variable::Height => car()?.height,
+            Variable::BallDistance => car()?.ball_distance,
+            Variable::AirTime => car()?.air_time,
+            Variable::BallSpeed => self.ball_speed,
+        })
+    }
+}
+
+/// Time series of the received game states, only recorded while the window is open
+#[derive(Resource)]
+pub struct Telemetry {
+    pub open: bool,
+    paused: bool,
+    window_seconds: f32,
+    samples: VecDeque<Sample>,
+    hidden_cars: HashSet<u32>,
+    variables: HashSet<Variable>,
+}
+
+impl Default for Telemetry {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            open: false,
+            paused: false,
+            window_seconds: 10.,
+            samples: VecDeque::new(),
+            hidden_cars: HashSet::default(),
+            variables: HashSet::from_iter([Variable::Speed, Variable::Boost]),
+        }
+    }
+}
+
+impl Telemetry {
+    fn push(&mut self, sample: Sample) {
+        let oldest = sample.time - f64::from(self.window_seconds);
+        self.samples.push_back(sample);
+
+        while self.samples.front().is_some_and(|first| first.time < oldest) {
+            self.samples.pop_front();
+        }
+    }
+
+    fn car_ids(&self) -> Vec<u32> {
+        let mut ids = self
+            .samples
+            .back()
+            .map(|sample| sample.cars.iter().map(|car| car.id).collect::<Vec<_>>())
+            .unwrap_or_default();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// The points of `variable` for `car_id`, with x being seconds before the latest sample
+    fn points(&self, variable: Variable, car_id: u32) -> PlotPoints {
+        let latest = self.samples.back().map_or(0., |sample| sample.time);
+
+        self.samples
+            .iter()
+            .filter_map(|sample| {
+                sample
+                    .value(variable, car_id)
+                    .map(|value| [sample.time - latest, f64::from(value)])
+            })
+            .collect()
+    }
+}
+
+fn record_telemetry(mut new_game_states: EventReader<NewGameState>, mut telemetry: ResMut<Telemetry>) {
+    if !telemetry.open {
+        // don't show old data the next time the window is opened
+        if !telemetry.samples.is_empty() {
+            telemetry.samples.clear();
+        }
+
+        return;
+    }
+
+    for NewGameState { state, reset } in new_game_states.read() {
+        if *reset {
+            telemetry.samples.clear();
+        }
+
+        // there's no time to plot against without a tick rate
+        if state.tick_rate <= 0. || telemetry.paused {
+            continue;
+        }
+
+        telemetry.push(Sample::new(state));
+    }
+}
+
+fn telemetry_ui(mut contexts: EguiContexts, mut telemetry: ResMut<Telemetry>) {
+    if !telemetry.open {
+        return;
+    }
+
+    let ctx = contexts.ctx_mut();
+    let telemetry = telemetry.as_mut();
+    let mut open = true;
+
+    egui::Window::new("Telemetry").open(&mut open).show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut telemetry.paused, "Pause");
+            ui.add(egui::Slider::new(&mut telemetry.window_seconds, 1.0..=MAX_WINDOW_SECONDS).text("Window (s)"));
+        });
+
+        let car_ids = telemetry.car_ids();
+
+        ui.horizontal_wrapped(|ui| {
+            for &id in &car_ids {
+                let mut shown = !telemetry.hidden_cars.contains(&id);
+                if ui.checkbox(&mut shown, format!("Car {id}")).changed() {
+                    if shown {
+                        telemetry.hidden_cars.remove(&id);
+                    } else {
+                        telemetry.hidden_cars.insert(id);
+                    }
+                }
+            }
+        });
+
+        ui.horizontal_wrapped(|ui| {
+            for variable in Variable::ALL {
+                let mut shown = telemetry.variables.contains(&variable);
+                if ui.checkbox(&mut shown, variable.name()).changed() {
+                    if shown {
+                        telemetry.variables.insert(variable);
+                    } else {
+                        telemetry.variables.remove(&variable);
+                    }
+                }
+            }
+        });
+
+        for variable in Variable::ALL {
+            if !telemetry.variables.contains(&variable) {
+                continue;
+            }
+
+            ui.label(variable.name());
+
+            Plot::new(variable.name())
+                .height(PLOT_HEIGHT)
+                .legend(Legend::default())
+                .include_x(-f64::from(telemetry.window_seconds))
+                .include_x(0.)
+                .allow_drag(false)
+                .allow_zoom(false)
+                .allow_scroll(false)
+                .show(ui, |plot_ui| {
+                    if !variable.is_per_car() {
+                        plot_ui.line(Line::new(telemetry.points(variable, 0)).name("Ball"));
+                        return;
+                    }
+
+                    for &id in car_ids.iter().filter(|id| !telemetry.hidden_cars.contains(id)) {
+                        plot_ui.line(Line::new(telemetry.points(variable, id)).name(format!("Car {id}")));
+                    }
+                });
+        }
+    });
+
+    telemetry.open = open;
+}
+
+pub struct TelemetryPlugin;
+
+impl Plugin for TelemetryPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Telemetry::default())
+            .add_systems(Update, (record_telemetry, telemetry_ui).chain());
+    }
+}
@@ -0,0 +1,219 @@
+use crate::{
+    rocketsim::Team,
+    settings::options::Options,
+    udp::{get_color_from_team, Ball, NewGameState, ToBevyVec},
+};
+use bevy::{
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+    utils::HashMap,
+};
+use std::{collections::VecDeque, time::Duration};
+
+/// How many touches are kept for the touch history panel
+const MAX_TOUCHES: usize = 50;
+const MARKER_DURATION: Duration = Duration::from_millis(1500);
+const MARKER_RADIUS: f32 = 12.;
+/// Length of the impulse arrow per uu/s of extra velocity given to the ball
+const IMPULSE_ARROW_SCALE: f32 = 0.5;
+const FLASH_DURATION: Duration = Duration::from_millis(300);
+/// The flash is a shell just outside of the ball
+const FLASH_SCALE: f32 = 1.1;
+
+/// A car hitting the ball, as reported by the simulation
+#[derive(Clone, Copy, Debug)]
+pub struct Touch {
+    pub car_id: u32,
+    pub team: Team,
+    pub tick_count: u64,
+    pub ball_speed_before: f32,
+    pub ball_speed_after: f32,
+}
+
+#[derive(Resource, Default)]
+pub struct TouchHistory(VecDeque<Touch>);
+
+impl TouchHistory {
+    fn push(&mut self, touch: Touch) {
+        if self.0.len() == MAX_TOUCHES {
+            self.0.pop_back();
+        }
+
+        self.0.push_front(touch);
+    }
+
+    /// Newest touch first
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &Touch> {
+        self.0.iter()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Where the ball was hit, and the extra velocity the hit gave it
+struct TouchMarker {
+    contact: Vec3,
+    impulse: Vec3,
+    color: Color,
+    timer: Timer,
+}
+
+#[derive(Resource, Default)]
+struct TouchMarkers(Vec<TouchMarker>);
+
+#[derive(Resource)]
+struct TouchFlashMesh(Handle<Mesh>);
+
+/// Briefly lights up the ball in the color of the team that touched it
+#[derive(Component)]
+struct TouchFlash(Timer);
+
+fn setup_touch_flash(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(TouchFlashMesh(meshes.add(Sphere::new(1.))));
+}
+
+/// What the previous state looked like, to tell which hits are new
+#[derive(Default)]
+struct LastState {
+    ball_speed: f32,
+    hit_ticks: HashMap<u32, u64>,
+}
+
+fn detect_touches(
+    mut commands: Commands,
+    options: Res<Options>,
+    flash_mesh: Res<TouchFlashMesh>,
+    mut new_game_states: EventReader<NewGameState>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut history: ResMut<TouchHistory>,
+    mut markers: ResMut<TouchMarkers>,
+    mut last: Local<LastState>,
+) {
+    for NewGameState { state, reset } in new_game_states.read() {
+        if *reset {
+            last.hit_ticks.clear();
+        }
+
+        let ball_speed = state.ball.vel.length();
+
+        for car in state.cars.iter() {
+            let hit = &car.state.ball_hit_info;
+            let previous_tick = last.hit_ticks.insert(car.id, hit.tick_count_when_hit);
+
+            // cars that haven't been seen before touched the ball before we started watching
+            if !hit.is_valid || previous_tick.map_or(true, |tick| tick == hit.tick_count_when_hit) {
+                continue;
+            }
+
+            history.push(Touch {
+                car_id: car.id,
+                team: car.team,
+                tick_count: hit.tick_count_when_hit,
+                ball_speed_before: last.ball_speed,
+                ball_speed_after: ball_speed,
+            });
+
+            if !options.touch_markers {
+                continue;
+            }
+
+            let color = get_color_from_team(car.team);
+
+            markers.0.push(TouchMarker {
+                contact: (hit.ball_pos + hit.relative_pos_on_ball).to_bevy(),
+                impulse: hit.extra_hit_vel.to_bevy(),
+                color,
+                timer: Timer::new(MARKER_DURATION, TimerMode::Once),
+            });
+
+            // the contact point is on the surface of the ball, so this works for every ball size
+            let radius = hit.relative_pos_on_ball.length();
+
+            commands.spawn((
+                PbrBundle {
+                    mesh: flash_mesh.0.clone(),
+                    material: materials.add(StandardMaterial {
+                        base_color: color,
+                        alpha_mode: AlphaMode::Add,
+                        unlit: true,
+                        ..default()
+                    }),
+                    transform: Transform::from_translation(state.ball.pos.to_bevy())
+                        .with_scale(Vec3::splat(radius * FLASH_SCALE)),
+                    ..default()
+                },
+                NotShadowCaster,
+                NotShadowReceiver,
+                TouchFlash(Timer::new(FLASH_DURATION, TimerMode::Once)),
+            ));
+        }
+
+        last.ball_speed = ball_speed;
+    }
+}
+
+fn animate_touch_flashes(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    ball: Query<&Transform, (With<Ball>, Without<TouchFlash>)>,
+    mut flashes: Query<(Entity, &mut TouchFlash, &mut Transform, &Handle<StandardMaterial>)>,
+) {
+    let ball = ball.get_single().ok();
+
+    for (entity, mut flash, mut transform, material) in &mut flashes {
+        flash.0.tick(time.delta());
+
+        if flash.0.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        if let Some(ball) = ball {
+            transform.translation = ball.translation;
+        }
+
+        if let Some(material) = materials.get_mut(material) {
+            material.base_color.set_alpha(1. - flash.0.fraction());
+        }
+    }
+}
+
+fn draw_touch_markers(time: Res<Time>, options: Res<Options>, mut markers: ResMut<TouchMarkers>, mut gizmos: Gizmos) {
+    markers.0.retain_mut(|marker| !marker.timer.tick(time.delta()).finished());
+
+    if !options.touch_markers {
+        return;
+    }
+
+    for marker in &markers.0 {
+        let color = marker.color.with_alpha(1. - marker.timer.fraction());
+
+        gizmos.sphere(marker.contact, Quat::IDENTITY, MARKER_RADIUS, color);
+
+        if marker.impulse != Vec3::ZERO {
+            gizmos.arrow(marker.contact, marker.contact + marker.impulse * IMPULSE_ARROW_SCALE, color);
+        }
+    }
+}
+
+pub struct TouchesPlugin;
+
+impl Plugin for TouchesPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TouchHistory::default())
+            .insert_resource(TouchMarkers::default())
+            .add_systems(Startup, setup_touch_flash)
+            .add_systems(Update, detect_touches)
+            .add_systems(PostUpdate, (animate_touch_flashes, draw_touch_markers));
+    }
+}
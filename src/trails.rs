@@ -0,0 +1,81 @@
+use crate::{
+    history::StateHistory,
+    rocketsim::Team,
+    settings::options::Options,
+    udp::{Ball, Car, GameStateApplied, GameStates, ToBevyVec},
+};
+use bevy::prelude::*;
+use std::{iter, time::Duration};
+
+/// `history` is oldest first, and the trail ends at `head` where the entity is drawn this frame
+fn draw_trail(gizmos: &mut Gizmos, history: impl Iterator<Item = Vec3>, head: Vec3, color: [f32; 3], fade: bool) {
+    let points = history.chain(iter::once(head)).collect::<Vec<_>>();
+    let last = points.len().saturating_sub(1).max(1) as f32;
+    let color = Color::srgb_from_array(color);
+
+    gizmos.linestrip_gradient(points.into_iter().enumerate().map(|(i, point)| {
+        // the trail fades out towards its oldest point
+        let alpha = if fade { i as f32 / last } else { 1. };
+        (point, color.with_alpha(alpha))
+    }));
+}
+
+fn draw_trails(
+    options: Res<Options>,
+    history: Res<StateHistory>,
+    states: Res<GameStates>,
+    ball: Query<&Transform, With<Ball>>,
+    cars: Query<(&Transform, &Car)>,
+    mut gizmos: Gizmos,
+) {
+    let max_age = Duration::try_from_secs_f32(options.trail_seconds).unwrap_or_default();
+    let recent = history.recent(max_age);
+
+    if options.ball_trail {
+        if let Ok(ball) = ball.get_single() {
+            let history = recent.clone().map(|state| state.ball.pos.to_bevy());
+            draw_trail(
+                &mut gizmos,
+                history,
+                ball.translation,
+                options.ball_trail_color,
+                options.trail_fade,
+            );
+        }
+    }
+
+    if options.car_trails {
+        for (car, id) in &cars {
+            let Some(info) = states.current.cars.iter().find(|info| info.id == id.id()) else {
+                continue;
+            };
+
+            let color = match info.team {
+                Team::Blue => options.blue_trail_color,
+                Team::Orange => options.orange_trail_color,
+            };
+
+            let history = recent.clone().filter_map(|state| {
+                state
+                    .cars
+                    .iter()
+                    .find(|info| info.id == id.id())
+                    .map(|info| info.state.pos.to_bevy())
+            });
+            draw_trail(&mut gizmos, history, car.translation, color, options.trail_fade);
+        }
+    }
+}
+
+pub struct TrailsPlugin;
+
+impl Plugin for TrailsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            draw_trails
+                .run_if(|options: Res<Options>| options.ball_trail || options.car_trails)
+                .in_set(GameStateApplied),
+        );
+    }
+}
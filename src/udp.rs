@@ -1,15 +1,19 @@
 use crate::{
     assets::{get_material, get_mesh_info, BoostPickupGlows, CarWheelMesh},
-    bytes::{FromBytes, ToBytes, ToBytesExact},
+    bytes::{DecodeError, FromBytes, FromBytesExact, ToBytes, ToBytesExact},
     camera::{PrimaryCamera, TimeDisplay},
+    history::StateHistory,
     mesh::LargeBoostPadLocRots,
     morton::Morton,
+    peers::{Peer, Peers},
     renderer::{RenderGroups, RenderMessage, UdpRendererPlugin},
+    replay::{self, Recorder, ReplayPlayback},
     rocketsim::{CarInfo, GameMode, GameState, Team},
     settings::options::{BallCam, CalcBallRot, GameSpeed, Options, PacketSmoothing, ShowTime},
-    GameLoadState, ServerPort,
+    stream::{self, Transport},
+    GameLoadState, ServerConfig,
 };
-use ahash::HashMap;
+use ahash::{HashMap, HashSet};
 use bevy::{
     app::AppExit,
     asset::LoadState,
@@ -22,11 +26,13 @@ use crossbeam_channel::{Receiver, Sender};
 use itertools::izip;
 use std::{
     f32::consts::PI,
-    fs,
+    fmt::Display,
+    fs, io,
     mem::{replace, swap},
-    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    net::{SocketAddr, UdpSocket},
+    path::Path,
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::{
@@ -75,20 +81,92 @@ pub enum SendableUdp {
     Paused(bool),
     Speed(f32),
     State(GameState),
+    /// Send everything after this to a different simulation
+    Target(Peer),
+    /// Send our handshake to a simulation, along with the one it sent if this is a reply
+    Handshake(Peer, Option<Handshake>),
 }
 
-fn establish_connection(port: Res<ServerPort>, mut commands: Commands, mut state: ResMut<NextState<GameLoadState>>) {
-    let out_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port.primary_port);
-    let recv_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port.secondary_port);
-    let socket = UdpSocket::bind(recv_addr).unwrap();
+impl SendableUdp {
+    /// The packet type and payload to send, if this is sent to the simulation at all
+    pub fn to_packet(&self) -> Option<(UdpPacketTypes, Vec<u8>)> {
+        match self {
+            Self::Paused(paused) => Some((UdpPacketTypes::Paused, vec![*paused as u8])),
+            Self::Speed(speed) => Some((UdpPacketTypes::Speed, speed.to_bytes().to_vec())),
+            Self::State(state) => Some((UdpPacketTypes::GameState, state.to_bytes())),
+            Self::Handshake(..) => Some((UdpPacketTypes::Handshake, Handshake::CURRENT.to_bytes().to_vec())),
+            Self::Target(_) => None,
+        }
+    }
+}
+
+/// Where packets come from, opened before the app starts so that a bad address is reported instead of panicking
+#[derive(Resource)]
+pub enum PacketSource {
+    Replay(Vec<replay::ReplayEntry>),
+    Live(Sender<SendableUdp>, Receiver<PeerUpdate>),
+}
+
+impl PacketSource {
+    /// Play back the replay file if there is one and it can be read, and otherwise listen for simulations
+    pub fn open(replay_file: Option<&Path>, config: &ServerConfig, transport: &Transport) -> io::Result<Self> {
+        if let Some(path) = replay_file {
+            match replay::read_replay(path) {
+                Ok(entries) => return Ok(Self::Replay(entries)),
+                Err(e) => println!(
+                    "Failed to read replay file {} due to: {e}, waiting for a simulation instead",
+                    path.display()
+                ),
+            }
+        }
+
+        let (outgoing, updates) = connect(config, transport)?;
+        Ok(Self::Live(outgoing, updates))
+    }
+}
+
+fn establish_connection(world: &mut World) {
+    match world.remove_resource::<PacketSource>() {
+        Some(PacketSource::Replay(entries)) => start_replay_handler(entries, world),
+        Some(PacketSource::Live(outgoing, updates)) => {
+            world.insert_resource(Connection(outgoing));
+            world.insert_resource(UdpUpdateStream(updates));
+        }
+        None => {}
+    }
+
+    world
+        .resource_mut::<NextState<GameLoadState>>()
+        .set(GameLoadState::FieldExtra);
+}
 
+/// Add what was being done to an error, since the error alone doesn't say which address or path it's about
+fn with_context(what: impl Display) -> impl FnOnce(io::Error) -> io::Error {
+    move |e| io::Error::new(e.kind(), format!("{what}: {e}"))
+}
+
+/// Start sending and receiving packets in the background
+pub fn connect(config: &ServerConfig, transport: &Transport) -> io::Result<(Sender<SendableUdp>, Receiver<PeerUpdate>)> {
     let (tx, rx) = crossbeam_channel::unbounded();
-    commands.insert_resource(Connection(tx));
+    let bind_addr = config.bind_addr().map_err(with_context("Invalid bind address"))?;
+
+    if let Transport::Stream(transport) = transport {
+        let (updates_tx, updates_rx) = crossbeam_channel::unbounded();
 
-    start_udp_recv_handler(socket.try_clone().unwrap(), &mut commands);
+        stream::listen(transport, bind_addr, updates_tx, rx)
+            .map_err(with_context(format!("Failed to listen for connections on {bind_addr}")))?;
+
+        return Ok((tx, updates_rx));
+    }
+
+    let out_addr = config.target_addr().map_err(with_context("Invalid target address"))?;
+    let socket = UdpSocket::bind(bind_addr).map_err(with_context(format!("Failed to bind to {bind_addr}")))?;
+    info!("Listening on {bind_addr}, sending to {out_addr}");
+
+    let updates = start_udp_recv_handler(socket.try_clone()?);
     start_udp_send_handler(socket, out_addr, rx);
 
-    state.set(GameLoadState::FieldExtra);
+    Ok((tx, updates))
 }
 
 pub trait ToBevyVec {
@@ -127,7 +205,7 @@ impl ToBevyVec for Vec3 {
     }
 }
 
-trait ToBevyMat {
+pub trait ToBevyMat {
     fn to_bevy(self) -> Quat;
 }
 
@@ -175,7 +253,7 @@ pub const ORANGE_COLOR: Srgba = if cfg!(feature = "full_load") {
 
 #[inline]
 /// Use colors that are a bit darker if we don't have the `full_load` feature
-const fn get_color_from_team(team: Team) -> Color {
+pub const fn get_color_from_team(team: Team) -> Color {
     match team {
         Team::Blue => Color::Srgba(BLUE_COLOR),
         Team::Orange => Color::Srgba(ORANGE_COLOR),
@@ -379,6 +457,7 @@ fn get_car_mesh_materials(
 }
 
 #[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum UdpPacketTypes {
     Quit,
     GameState,
@@ -386,10 +465,11 @@ pub enum UdpPacketTypes {
     Paused,
     Speed,
     Render,
+    Handshake,
 }
 
 impl UdpPacketTypes {
-    const fn new(byte: u8) -> Option<Self> {
+    pub const fn new(byte: u8) -> Option<Self> {
         match byte {
             0 => Some(Self::Quit),
             1 => Some(Self::GameState),
@@ -397,9 +477,100 @@ impl UdpPacketTypes {
             3 => Some(Self::Paused),
             4 => Some(Self::Speed),
             5 => Some(Self::Render),
+            6 => Some(Self::Handshake),
             _ => None,
         }
     }
+
+    /// In the legacy format, whether a second datagram with the payload follows
+    const fn has_payload(self) -> bool {
+        !matches!(self, Self::Quit | Self::Connection)
+    }
+}
+
+/// Every framed datagram starts with these bytes, which can't be mistaken for a legacy packet type
+pub const FRAME_MAGIC: u16 = u16::from_le_bytes(*b"RV");
+
+/// The framed format sends an entire packet in one datagram, laid out as:
+///
+/// `[magic: u16][packet type: u8][payload length: u32][sequence number: u32][payload]`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub magic: u16,
+    pub packet_type: u8,
+    pub len: u32,
+    pub seq: u32,
+}
+
+impl FrameHeader {
+    /// `None` if the datagram isn't in the framed format
+    pub fn parse(datagram: &[u8]) -> Option<Result<(Self, &[u8]), DecodeError>> {
+        if !datagram.starts_with(&FRAME_MAGIC.to_le_bytes()) {
+            return None;
+        }
+
+        Some(Self::from_bytes(datagram).and_then(|header| {
+            let payload = &datagram[Self::NUM_BYTES..];
+
+            if payload.len() == header.len as usize {
+                Ok((header, payload))
+            } else {
+                Err(DecodeError::SizeMismatch {
+                    expected: Self::NUM_BYTES + header.len as usize,
+                    actual: datagram.len(),
+                })
+            }
+        }))
+    }
+
+    pub fn write(packet_type: UdpPacketTypes, seq: u32, payload: &[u8]) -> Vec<u8> {
+        let header = Self {
+            magic: FRAME_MAGIC,
+            packet_type: packet_type as u8,
+            len: payload.len() as u32,
+            seq,
+        };
+
+        let mut bytes = Vec::with_capacity(Self::NUM_BYTES + payload.len());
+        bytes.extend_from_slice(&header.to_bytes());
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+}
+
+/// Bumped whenever the packets change in a way that older clients can't handle
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Sent by both sides after the packet type byte of [`UdpPacketTypes::Handshake`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Handshake {
+    pub version: u16,
+    /// Bit flags of optional features that are supported
+    pub capabilities: u32,
+}
+
+impl Handshake {
+    /// Render messages are understood
+    pub const RENDER: u32 = 1 << 0;
+    /// Game states are sent back when the user moves things around
+    pub const STATE_SET: u32 = 1 << 1;
+    /// Packets can be sent in the single datagram [`FrameHeader`] format
+    pub const FRAMED: u32 = 1 << 2;
+
+    pub const CURRENT: Self = Self {
+        version: PROTOCOL_VERSION,
+        capabilities: Self::RENDER | Self::STATE_SET | Self::FRAMED,
+    };
+
+    #[inline]
+    pub const fn is_compatible(&self) -> bool {
+        self.version == PROTOCOL_VERSION
+    }
+
+    #[inline]
+    pub const fn supports(&self, capability: u32) -> bool {
+        self.capabilities & capability != 0
+    }
 }
 
 #[derive(Event)]
@@ -408,185 +579,220 @@ pub struct SpeedUpdate(pub f32);
 #[derive(Event)]
 pub struct PausedUpdate(pub bool);
 
-enum UdpUpdate {
-    State(GameState),
-    Render(RenderMessage),
+/// Sent for every game state received from the simulation on screen, even when several arrive in one frame
+///
+/// The state is exactly what was received, unlike `GameStates::current` which may be interpolated or extrapolated.
+#[derive(Event)]
+pub struct NewGameState {
+    pub state: GameState,
+    /// The tick count went backwards or another simulation is being shown,
+    /// so anything gathered from the previous states doesn't carry over
+    pub reset: bool,
+}
+
+pub enum UdpUpdate {
+    State(GameState, Instant),
+    Render(RenderMessage, Instant),
     Speed(f32),
     Paused(bool),
     Connection,
+    Handshake(Handshake),
+    /// A packet that couldn't be decoded and was dropped
+    BadPacket(DecodeError),
+    /// The stream connection closed without the simulation quitting
+    Disconnected,
     Exit,
 }
 
-#[derive(Resource, Deref)]
-struct UdpUpdateStream(Receiver<UdpUpdate>);
+impl UdpUpdate {
+    /// The payload of each packet type is the same in every format
+    pub fn decode(packet_type: UdpPacketTypes, payload: &[u8], received: Instant) -> Self {
+        let update = match packet_type {
+            UdpPacketTypes::Quit => Ok(Self::Exit),
+            UdpPacketTypes::Connection => Ok(Self::Connection),
+            UdpPacketTypes::GameState => GameState::from_bytes(payload).map(|state| Self::State(state, received)),
+            UdpPacketTypes::Render => {
+                RenderMessage::from_bytes(payload).map(|render_message| Self::Render(render_message, received))
+            }
+            UdpPacketTypes::Speed => f32::from_bytes(payload).map(Self::Speed),
+            UdpPacketTypes::Paused => bool::from_bytes(payload).map(Self::Paused),
+            UdpPacketTypes::Handshake => Handshake::from_bytes(payload).map(Self::Handshake),
+        };
 
-fn start_udp_send_handler(socket: UdpSocket, out_addr: SocketAddr, outgoing: Receiver<SendableUdp>) {
-    socket.send_to(&[UdpPacketTypes::Connection as u8], out_addr).unwrap();
+        update.unwrap_or_else(Self::BadPacket)
+    }
+}
 
-    thread::spawn(move || loop {
-        match outgoing.recv() {
-            Ok(SendableUdp::State(state)) => {
-                let bytes = state.to_bytes();
+/// The largest payload that fits in a UDP datagram
+const MAX_DATAGRAM_BYTES: usize = 65_507;
 
-                if socket.send_to(&[UdpPacketTypes::GameState as u8], out_addr).is_err() {
-                    continue;
-                }
+/// An update along with the simulation that sent it
+pub struct PeerUpdate {
+    pub peer: Peer,
+    pub update: UdpUpdate,
+}
 
-                if socket.send_to(&bytes, out_addr).is_err() {
-                    continue;
-                }
-            }
-            Ok(SendableUdp::Speed(speed)) => {
-                let bytes = speed.to_bytes();
+#[derive(Resource, Deref)]
+struct UdpUpdateStream(Receiver<PeerUpdate>);
 
-                if socket.send_to(&[UdpPacketTypes::Speed as u8], out_addr).is_err() {
-                    continue;
-                }
+fn start_udp_send_handler(socket: UdpSocket, mut out_addr: SocketAddr, outgoing: Receiver<SendableUdp>) {
+    if let Err(e) = socket.send_to(&[UdpPacketTypes::Connection as u8], out_addr) {
+        error!("Failed to connect to {out_addr}: {e}");
+    }
 
-                if socket.send_to(&bytes, out_addr).is_err() {
+    thread::spawn(move || {
+        // simulations that said they understand the framed format in their handshake
+        let mut framed_addrs = HashSet::<SocketAddr>::default();
+        // each simulation gets its own sequence, so it doesn't see gaps from packets sent to the others
+        let mut seqs = HashMap::<SocketAddr, u32>::default();
+
+        for sendable in outgoing {
+            let addr = match sendable {
+                SendableUdp::Target(Peer::Addr(addr)) => {
+                    out_addr = addr;
+                    drop(socket.send_to(&[UdpPacketTypes::Connection as u8], out_addr));
                     continue;
                 }
-            }
-            Ok(SendableUdp::Paused(paused)) => {
-                let paused = [paused as u8];
+                SendableUdp::Handshake(Peer::Addr(addr), handshake) => {
+                    if let Some(handshake) = handshake {
+                        if handshake.supports(Handshake::FRAMED) {
+                            framed_addrs.insert(addr);
+                        } else {
+                            framed_addrs.remove(&addr);
+                        }
+                    }
 
-                if socket.send_to(&[UdpPacketTypes::Paused as u8], out_addr).is_err() {
-                    continue;
+                    addr
                 }
+                SendableUdp::Target(_) | SendableUdp::Handshake(..) => continue,
+                _ => out_addr,
+            };
 
-                if socket.send_to(&paused, out_addr).is_err() {
-                    continue;
-                }
+            let Some((packet_type, bytes)) = sendable.to_packet() else {
+                continue;
+            };
+
+            if framed_addrs.contains(&addr) {
+                let seq = seqs.entry(addr).or_default();
+                drop(socket.send_to(&FrameHeader::write(packet_type, *seq, &bytes), addr));
+                *seq = seq.wrapping_add(1);
+                continue;
             }
-            Err(_) => return,
+
+            if socket.send_to(&[packet_type as u8], addr).is_err() {
+                continue;
+            }
+
+            drop(socket.send_to(&bytes, addr));
         }
     });
 }
 
-fn start_udp_recv_handler(socket: UdpSocket, commands: &mut Commands) {
+fn start_udp_recv_handler(socket: UdpSocket) -> Receiver<PeerUpdate> {
     let (tx, rx) = crossbeam_channel::unbounded();
 
     thread::spawn(move || {
-        let mut packet_type_buffer = [0];
-        let mut initial_state_buffer = [0; GameState::MIN_NUM_BYTES];
-        let mut initial_render_buffer = [0; RenderMessage::MIN_NUM_BYTES];
-        let mut speed_buffer = [0; 4];
-        let mut paused_buffer = [0];
+        // big enough for any datagram, so nothing ever gets cut off
+        let mut buf = vec![0; MAX_DATAGRAM_BYTES];
 
-        let mut buf = Vec::new();
-        let mut render_buf = Vec::new();
-        let mut last_game_state = GameState::default();
+        let mut last_tick_counts = HashMap::<SocketAddr, u64>::default();
+        let mut last_seqs = HashMap::<SocketAddr, u32>::default();
+        let mut unknown_packet_types = HashSet::<u8>::default();
+        // legacy peers that sent a packet type, whose payload is the next datagram from the same address
+        let mut awaiting_payload = HashMap::<SocketAddr, u8>::default();
 
         loop {
-            if socket.recv_from(&mut packet_type_buffer).is_err() {
-                return;
-            }
-
-            let Some(packet_type) = UdpPacketTypes::new(packet_type_buffer[0]) else {
+            let Ok((num_bytes, src_addr)) = socket.recv_from(&mut buf) else {
                 return;
             };
 
-            match packet_type {
-                UdpPacketTypes::Quit => {
-                    drop(tx.send(UdpUpdate::Exit));
-                    return;
-                }
-                UdpPacketTypes::GameState => {
-                    // wait until we receive the packet
-                    // it should arrive VERY quickly, so a loop with no delay is fine
-                    // if it doesn't, then there are other problems lol
-                    // UPDATE: Windows throws a specific error that we need to look for
-                    // despite the fact that it actually worked
-
-                    #[cfg(windows)]
-                    {
-                        while let Err(e) = socket.peek_from(&mut initial_state_buffer) {
-                            if let Some(code) = e.raw_os_error() {
-                                if code == 10040 {
-                                    break;
-                                }
-                            }
-                        }
-                    }
-
-                    #[cfg(not(windows))]
-                    {
-                        while socket.peek_from(&mut initial_state_buffer).is_err() {}
-                    }
-
-                    let new_tick_count = GameState::read_tick_count(&initial_state_buffer);
-                    if new_tick_count > 1 && last_game_state.tick_count > new_tick_count {
-                        drop(socket.recv_from(&mut [0]));
-                        return;
-                    }
+            let datagram = &buf[..num_bytes];
+            let peer = Peer::Addr(src_addr);
 
-                    buf.resize(GameState::get_num_bytes(&initial_state_buffer), 0);
-                    if socket.recv_from(&mut buf).is_err() {
-                        return;
-                    }
+            let send = |update| tx.send(PeerUpdate { peer, update }).is_ok();
 
-                    last_game_state = GameState::from_bytes(&buf);
-                    if tx.send(UdpUpdate::State(last_game_state.clone())).is_err() {
-                        return;
-                    }
+            // datagrams from other peers can arrive between a legacy packet type and its payload,
+            // so the payload is matched up by its address instead of being whatever arrives next
+            let (packet_type, payload) = if let Some(packet_type) = awaiting_payload.remove(&src_addr) {
+                (packet_type, datagram)
+            } else if let [packet_type] = *datagram {
+                // the legacy format, where the payload is sent in a second datagram
+                if UdpPacketTypes::new(packet_type).is_some_and(UdpPacketTypes::has_payload) {
+                    awaiting_payload.insert(src_addr, packet_type);
+                    continue;
                 }
-                UdpPacketTypes::Render => {
-                    #[cfg(windows)]
-                    {
-                        while let Err(e) = socket.peek_from(&mut initial_render_buffer) {
-                            if let Some(code) = e.raw_os_error() {
-                                if code == 10040 {
-                                    break;
-                                }
-                            }
-                        }
-                    }
 
-                    #[cfg(not(windows))]
-                    {
-                        while socket.peek_from(&mut initial_render_buffer).is_err() {}
-                    }
+                (packet_type, &[][..])
+            } else {
+                match FrameHeader::parse(datagram) {
+                    Some(Ok((header, payload))) => {
+                        // drop frames that arrived out of order, but a sequence number of 0 means the sender restarted
+                        let last_seq = last_seqs.entry(src_addr).or_insert(0);
+                        if header.seq != 0 && (header.seq.wrapping_sub(*last_seq) as i32) <= 0 {
+                            continue;
+                        }
 
-                    render_buf.resize(RenderMessage::get_num_bytes(&initial_render_buffer), 0);
-                    if socket.recv_from(&mut render_buf).is_err() {
-                        return;
+                        *last_seq = header.seq;
+                        (header.packet_type, payload)
                     }
+                    Some(Err(e)) => {
+                        if !send(UdpUpdate::BadPacket(e)) {
+                            return;
+                        }
 
-                    let render_message = RenderMessage::from_bytes(&render_buf);
-                    if tx.send(UdpUpdate::Render(render_message)).is_err() {
-                        return;
+                        continue;
                     }
+                    // the payload of a legacy packet with an unknown type
+                    None => continue,
                 }
-                UdpPacketTypes::Speed => {
-                    if socket.recv_from(&mut speed_buffer).is_err() {
-                        return;
-                    }
+            };
 
-                    let speed = f32::from_le_bytes(speed_buffer);
-                    if tx.send(UdpUpdate::Speed(speed)).is_err() {
-                        return;
-                    }
+            let Some(packet_type) = UdpPacketTypes::new(packet_type) else {
+                // most likely a packet from a newer version, so skip it instead of giving up
+                if unknown_packet_types.insert(packet_type) {
+                    warn!("Skipping unknown packet type {packet_type} from {src_addr}, the sender may be using a newer protocol");
                 }
-                UdpPacketTypes::Paused => {
-                    if socket.recv_from(&mut paused_buffer).is_err() {
-                        return;
-                    }
 
-                    let paused = paused_buffer[0] != 0;
-                    if tx.send(UdpUpdate::Paused(paused)).is_err() {
-                        return;
+                continue;
+            };
+
+            let update = UdpUpdate::decode(packet_type, payload, Instant::now());
+
+            match &update {
+                UdpUpdate::State(game_state, _) => {
+                    // each simulation has its own tick count, so only compare against the same sender
+                    let last_tick_count = last_tick_counts.entry(src_addr).or_default();
+                    if game_state.tick_count > 1 && *last_tick_count > game_state.tick_count {
+                        continue;
                     }
+
+                    *last_tick_count = game_state.tick_count;
                 }
-                UdpPacketTypes::Connection => {
-                    if tx.send(UdpUpdate::Connection).is_err() {
-                        return;
-                    }
+                UdpUpdate::Exit => {
+                    last_tick_counts.remove(&src_addr);
+                    last_seqs.remove(&src_addr);
                 }
+                _ => {}
+            }
+
+            if !send(update) {
+                return;
             }
         }
     });
 
-    commands.insert_resource(UdpUpdateStream(rx));
+    rx
+}
+
+fn start_replay_handler(entries: Vec<replay::ReplayEntry>, world: &mut World) {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    world.insert_resource(UdpUpdateStream(rx));
+    world.insert_resource(ReplayPlayback::new(entries, tx));
+
+    // there's no simulation to send requests to, so discard them
+    let (tx, rx) = crossbeam_channel::unbounded();
+    world.insert_resource(Connection(tx));
+    thread::spawn(move || for _ in rx {});
 }
 
 fn apply_udp_updates(
@@ -602,30 +808,113 @@ fn apply_udp_updates(
     mut render_groups: ResMut<RenderGroups>,
     mut packet_time_elapsed: ResMut<PacketTimeElapsed>,
     mut last_packet_time_elapsed: ResMut<LastPacketTimesElapsed>,
-    mut speed_update: EventWriter<SpeedUpdate>,
-    mut paused_update: EventWriter<PausedUpdate>,
+    (mut speed_update, mut paused_update, mut new_game_states): (
+        EventWriter<SpeedUpdate>,
+        EventWriter<PausedUpdate>,
+        EventWriter<NewGameState>,
+    ),
+    mut recorder: ResMut<Recorder>,
+    mut history: ResMut<StateHistory>,
+    mut peers: ResMut<Peers>,
 ) {
     packet_time_elapsed.tick(time.delta());
 
     let mut new_game_state = None;
 
-    for update in udp_updates.try_iter() {
+    for PeerUpdate { peer, update } in udp_updates.try_iter() {
+        if !matches!(update, UdpUpdate::Exit | UdpUpdate::Disconnected) {
+            let info = peers.seen(peer);
+            match &update {
+                UdpUpdate::State(new_state, _) => info.update(new_state),
+                UdpUpdate::BadPacket(e) => {
+                    // only log the first one, the rest are counted in the menu
+                    if info.num_bad_packets == 0 {
+                        error!("Dropped a bad packet from {peer}: {e}");
+                    }
+
+                    info.num_bad_packets += 1;
+                    info.last_error = Some(e.clone());
+                }
+                UdpUpdate::Handshake(handshake) => {
+                    info.handshake = Some(*handshake);
+
+                    if !handshake.is_compatible() {
+                        error!(
+                            "{peer} uses protocol version {}, but this version of RLViser uses version {PROTOCOL_VERSION}, \
+                             so its packets will be ignored. Update RLViser and the simulation so that they match!",
+                            handshake.version
+                        );
+                    }
+
+                    // let the other side check our version too
+                    socket.send(SendableUdp::Handshake(peer, Some(*handshake))).unwrap();
+                }
+                UdpUpdate::Connection => {
+                    // simulations that don't start with a handshake can still check our version
+                    socket.send(SendableUdp::Handshake(peer, None)).unwrap();
+                }
+                _ => {}
+            }
+
+            // the packets of a simulation with a different protocol version can't be understood
+            let compatible = info.is_compatible();
+
+            // other simulations are tracked, but only the one on screen gets applied
+            if !compatible || !peers.is_active(peer) {
+                continue;
+            }
+        }
+
         match update {
+            UdpUpdate::Disconnected => {
+                info!("{peer} disconnected");
+
+                // the next simulation to be displayed gets a clean slate
+                if peers.is_active(peer) {
+                    render_groups.clear();
+                }
+
+                peers.remove(peer);
+            }
             UdpUpdate::Exit => {
-                exit.send(AppExit::Success);
-                return;
+                if peers.is_active(peer) {
+                    render_groups.clear();
+                }
+
+                peers.remove(peer);
+
+                // only close once every simulation has quit
+                if peers.is_empty() {
+                    exit.send(AppExit::Success);
+                    return;
+                }
             }
-            UdpUpdate::State(new_state) => {
+            UdpUpdate::State(new_state, received) => {
+                // a replay that's being played back would only be recorded again
+                if peer != Peer::Replay {
+                    recorder.record_state(received, &new_state);
+                }
+
+                // the history is cleared when another simulation is shown
+                let reset = history
+                    .latest()
+                    .map_or(true, |latest| new_state.tick_count < latest.tick_count);
+
+                history.push(received, new_state.clone());
+                new_game_states.send(NewGameState {
+                    state: new_state.clone(),
+                    reset,
+                });
                 new_game_state = Some(new_state);
             }
-            UdpUpdate::Render(render_message) => match render_message {
-                RenderMessage::AddRender(group_id, renders) => {
-                    render_groups.groups.insert(group_id, renders);
+            UdpUpdate::Render(render_message, received) => {
+                if peer != Peer::Replay {
+                    recorder.record_render(received, &render_message);
                 }
-                RenderMessage::RemoveRender(group_id) => {
-                    render_groups.groups.remove(&group_id);
-                }
-            },
+
+                let tick_count = new_game_state.as_ref().unwrap_or(&game_states.current).tick_count;
+                render_groups.apply(render_message, tick_count);
+            }
             UdpUpdate::Speed(speed) => {
                 speed_update.send(SpeedUpdate(speed));
             }
@@ -633,12 +922,33 @@ fn apply_udp_updates(
                 paused_update.send(PausedUpdate(paused));
             }
             UdpUpdate::Connection => {
+                // a restarted client won't remove what it rendered before
+                render_groups.clear();
+
                 socket.send(SendableUdp::Paused(game_speed.paused)).unwrap();
                 socket.send(SendableUdp::Speed(game_speed.speed)).unwrap();
             }
+            UdpUpdate::Handshake(_) | UdpUpdate::BadPacket(_) => {}
         }
     }
 
+    // a client that crashed can't clear its renders, so they would be stuck on screen
+    if peers.remove_stale() {
+        render_groups.clear();
+    }
+
+    let tick_count = new_game_state.as_ref().unwrap_or(&game_states.current).tick_count;
+    RenderGroups::update(&mut render_groups, |render_groups| render_groups.remove_expired(tick_count));
+
+    if let Some(viewed_state) = history.viewed_state() {
+        // keep showing the rewound state while the live states continue to be buffered
+        game_states.last = viewed_state.clone();
+        game_states.current = viewed_state.clone();
+        game_states.next = viewed_state.clone();
+        packet_updated.0 = true;
+        return;
+    }
+
     match new_game_state {
         Some(new_state) => {
             last_packet_time_elapsed.push(packet_time_elapsed.0.elapsed_secs());
@@ -653,6 +963,31 @@ fn apply_udp_updates(
     }
 }
 
+/// Reset everything that belonged to the previously displayed simulation
+fn switch_peer(
+    socket: Res<Connection>,
+    game_speed: Res<GameSpeed>,
+    mut peers: ResMut<Peers>,
+    mut render_groups: ResMut<RenderGroups>,
+    mut history: ResMut<StateHistory>,
+) {
+    let Some((peer, replaced)) = peers.take_changed() else {
+        return;
+    };
+
+    info!("Now displaying {peer}");
+
+    // the first peer to connect has nothing to clear, and its first renders have already been applied
+    if replaced {
+        render_groups.clear();
+        history.clear();
+    }
+
+    socket.send(SendableUdp::Target(peer)).unwrap();
+    socket.send(SendableUdp::Paused(game_speed.paused)).unwrap();
+    socket.send(SendableUdp::Speed(game_speed.speed)).unwrap();
+}
+
 fn update_ball(
     states: Res<GameStates>,
     mut ball: Query<(&mut Transform, &Children), With<Ball>>,
@@ -695,37 +1030,23 @@ fn update_car(states: Res<GameStates>, mut cars: Query<(&mut Transform, &Car)>)
 
 fn update_car_extra(
     states: Res<GameStates>,
-    car_entities: Query<(Entity, &Car)>,
-    mut cars: Query<(&Car, &Children)>,
+    mut cars: Query<(&Car, &Children, &mut Visibility)>,
     mut car_boosts: Query<&Handle<StandardMaterial>, With<CarBoost>>,
-    mut car_materials: Query<&Handle<StandardMaterial>, (With<Car>, Without<CarBoost>)>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut last_boost_states: Local<Vec<u32>>,
-    mut last_demoed_states: Local<Vec<u32>>,
     mut last_boost_amounts: Local<HashMap<u32, f32>>,
 ) {
-    for (car, children) in &mut cars {
+    for (car, children, mut visibility) in &mut cars {
         let Some(target_car) = states.current.cars.iter().find(|car_info| car.0 == car_info.id) else {
             continue;
         };
 
-        let last_demoed = last_demoed_states.iter().any(|&id| id == car.id());
-
-        if target_car.state.is_demoed != last_demoed {
-            for (entity, car) in &car_entities {
-                if car.0 == target_car.id {
-                    let material_handle = car_materials.get_mut(entity).unwrap();
-                    let material = materials.get_mut(material_handle).unwrap();
-                    if target_car.state.is_demoed {
-                        material.base_color.set_alpha(0.);
-                        last_demoed_states.push(car.id());
-                    } else {
-                        material.base_color.set_alpha(1.);
-                        last_demoed_states.retain(|&id| id != car.id());
-                    }
-                }
-            }
-        }
+        // the wheels and boost are hidden along with the body
+        visibility.set_if_neq(if target_car.state.is_demoed {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        });
 
         let last_boost_amount = last_boost_amounts
             .insert(car.id(), target_car.state.boost)
@@ -1121,6 +1442,21 @@ fn update_pad_colors(
     }
 }
 
+/// The id of the car that the camera is following, if any
+pub fn focused_car_id(camera: &PrimaryCamera, cars: &[CarInfo]) -> Option<u32> {
+    match camera {
+        PrimaryCamera::TrackCar(id) => {
+            // the number keys pick cars in order of their ids
+            let mut ids = cars.iter().map(|car_info| car_info.id).collect::<Vec<_>>();
+            radsort::sort(&mut ids);
+
+            (*id as usize).checked_sub(1).and_then(|index| ids.get(index)).copied()
+        }
+        PrimaryCamera::Director(id) => Some(*id),
+        PrimaryCamera::Spectator => None,
+    }
+}
+
 fn update_boost_meter(
     states: Res<GameStates>,
     ui_scale: Res<UiOverlayScale>,
@@ -1130,25 +1466,7 @@ fn update_boost_meter(
     mut boost_amount: Query<(&mut Text, &mut Style), With<BoostAmount>>,
     mut was_last_director: Local<bool>,
 ) {
-    let id = match camera.single() {
-        PrimaryCamera::TrackCar(id) => {
-            if states.current.cars.is_empty() {
-                return;
-            }
-
-            let mut ids = states.current.cars.iter().map(|car_info| car_info.id).collect::<Vec<_>>();
-            radsort::sort(&mut ids);
-
-            let index = *id as usize - 1;
-            if index >= ids.len() {
-                0
-            } else {
-                ids[index]
-            }
-        }
-        PrimaryCamera::Director(id) => *id,
-        PrimaryCamera::Spectator => 0,
-    };
+    let id = focused_car_id(camera.single(), &states.current.cars).unwrap_or_default();
 
     if id == 0 {
         if *was_last_director {
@@ -1413,6 +1731,11 @@ fn listen(
 #[derive(Resource, Default)]
 struct PacketUpdated(bool);
 
+/// Systems in `PostUpdate` that draw or place things where the cars and the ball are,
+/// which have been moved for this frame by then
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GameStateApplied;
+
 #[derive(Resource, Default)]
 pub struct GameStates {
     pub last: GameState,
@@ -1476,12 +1799,16 @@ impl Plugin for RocketSimPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<PausedUpdate>()
             .add_event::<SpeedUpdate>()
+            .add_event::<NewGameState>()
             .insert_resource(GameStates::default())
+            .insert_resource(StateHistory::default())
+            .insert_resource(Peers::default())
             .insert_resource(DirectorTimer(Timer::new(Duration::from_secs(12), TimerMode::Repeating)))
             .insert_resource(PacketTimeElapsed::default())
             .insert_resource(LastPacketTimesElapsed::default())
             .insert_resource(PacketUpdated::default())
             .insert_resource(GameMode::default())
+            .configure_sets(PostUpdate, GameStateApplied)
             .add_plugins(UdpRendererPlugin)
             .add_systems(
                 Update,
@@ -1489,7 +1816,12 @@ impl Plugin for RocketSimPlugin {
                     establish_connection.run_if(in_state(GameLoadState::Connect)),
                     (
                         (
-                            apply_udp_updates,
+                            (
+                                switch_peer,
+                                replay::advance_replay.run_if(resource_exists::<ReplayPlayback>),
+                                apply_udp_updates,
+                            )
+                                .chain(),
                             (
                                 (
                                     (
@@ -1541,3 +1873,48 @@ impl Plugin for RocketSimPlugin {
             );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_round_trip() {
+        let datagram = FrameHeader::write(UdpPacketTypes::Speed, 42, &1.5f32.to_bytes());
+        let (header, payload) = FrameHeader::parse(&datagram).unwrap().unwrap();
+
+        assert_eq!(header.magic, FRAME_MAGIC);
+        assert_eq!(header.packet_type, UdpPacketTypes::Speed as u8);
+        assert_eq!(header.len, 4);
+        assert_eq!(header.seq, 42);
+        assert_eq!(f32::from_bytes(payload), Ok(1.5));
+    }
+
+    #[test]
+    fn legacy_datagrams_are_not_frames() {
+        assert!(FrameHeader::parse(&[UdpPacketTypes::GameState as u8]).is_none());
+        assert!(FrameHeader::parse(&[]).is_none());
+    }
+
+    #[test]
+    fn truncated_frame_header() {
+        assert!(matches!(
+            FrameHeader::parse(&FRAME_MAGIC.to_le_bytes()),
+            Some(Err(DecodeError::Truncated { .. }))
+        ));
+    }
+
+    #[test]
+    fn frame_length_mismatch() {
+        let mut datagram = FrameHeader::write(UdpPacketTypes::Paused, 0, &[1]);
+        datagram.push(0);
+
+        assert_eq!(
+            FrameHeader::parse(&datagram),
+            Some(Err(DecodeError::SizeMismatch {
+                expected: FrameHeader::NUM_BYTES + 1,
+                actual: FrameHeader::NUM_BYTES + 2,
+            }))
+        );
+    }
+}